@@ -10,7 +10,6 @@ repositories {
 }
 
 dependencies {
-    implementation("net.fabricmc:mapping-io:0.6.1")
     implementation("org.jetbrains:annotations:15.0")
 }
 
@@ -1,9 +1,16 @@
 package com.codemob.mcconnect;
 
+import java.io.IOException;
+import java.io.InputStream;
 import java.lang.reflect.Field;
 import java.lang.reflect.Method;
+import java.nio.charset.StandardCharsets;
+import java.util.regex.Matcher;
+import java.util.regex.Pattern;
 
 public class MinecraftVersionResolver {
+    private static final Pattern VERSION_ID = Pattern.compile("\"id\"\\s*:\\s*\"([^\"]+)\"");
+
     public static String resolveVersion() {
         // We haven't initialized mappings yet, so we have to manually put in the unmapped names.
         try {
@@ -16,8 +23,27 @@ public class MinecraftVersionResolver {
             // String GameVersion.getName()
             Method get_name = game_version.getClass().getMethod("method_48019");
             return (String) get_name.invoke(game_version);
-        } catch (Exception e) {
-            throw new RuntimeException(e);
+        } catch (ReflectiveOperationException e) {
+            // Only Fabric runs with intermediary names, but every game jar has its version.json
+            try {
+                return readVersionJson();
+            } catch (IOException io) {
+                io.addSuppressed(e);
+                throw new RuntimeException(io);
+            }
+        }
+    }
+
+    private static String readVersionJson() throws IOException {
+        try (InputStream in = RustAgent.classLoader.getResourceAsStream("version.json")) {
+            if (in == null) {
+                throw new IOException("the game has no version.json");
+            }
+            Matcher matcher = VERSION_ID.matcher(new String(in.readAllBytes(), StandardCharsets.UTF_8));
+            if (!matcher.find()) {
+                throw new IOException("version.json has no id");
+            }
+            return matcher.group(1);
         }
     }
 }
@@ -0,0 +1,62 @@
+package com.codemob.mcconnect;
+
+import java.lang.ref.Cleaner;
+
+/**
+ * A Rust closure scheduled on one of the game's threads.
+ */
+public class NativeTask implements Runnable {
+    private static final Cleaner CLEANER = Cleaner.create();
+
+    private final Closure closure;
+
+    public NativeTask(long function, long data) {
+        this.closure = new Closure(function, data);
+        // A task the game throws away without running still has to free its closure, which
+        // tells whoever waits for it that it won't run.
+        CLEANER.register(this, closure);
+    }
+
+    @Override
+    public void run() {
+        closure.call(true);
+    }
+
+    /**
+     * Owns the closure, which is freed either by running it or by dropping it. It must not
+     * refer to its task, or the task would never be collected.
+     */
+    private static final class Closure implements Runnable {
+        private final long function;
+        private final long data;
+        private boolean done = false;
+
+        Closure(long function, long data) {
+            this.function = function;
+            this.data = data;
+        }
+
+        synchronized void call(boolean run) {
+            // The closure is freed either way, so calling it twice would be a use after free.
+            if (done) {
+                return;
+            }
+            done = true;
+            if (run) {
+                runNative(function, data);
+            } else {
+                dropNative(function, data);
+            }
+        }
+
+        /** Called by the cleaner once the task is unreachable. */
+        @Override
+        public void run() {
+            call(false);
+        }
+    }
+
+    private static native void runNative(long function, long data);
+
+    private static native void dropNative(long function, long data);
+}
@@ -2,20 +2,42 @@ package com.codemob.mcconnect;
 
 import java.lang.instrument.Instrumentation;
 import java.util.Arrays;
+import java.util.HashMap;
+import java.util.Map;
 
 public class RustAgent {
     public static YarnMappingResolver mappingResolver;
     public static String version;
     public static ClassLoader classLoader;
-    public static void agentmain(String agentArgs, Instrumentation inst) throws Exception {
+    private static boolean initialized = false;
+
+    /**
+     * @param agentArgs the path of the native library, then options as {@code ;key=value}, which
+     *                  are those of {@link YarnMappingResolver#YarnMappingResolver}
+     */
+    public static synchronized void agentmain(String agentArgs, Instrumentation inst) throws Exception {
+        // Every client attaches the agent again; they all share the listener started by the first one.
+        if (initialized) {
+            return;
+        }
         classLoader = Arrays.stream(inst.getAllLoadedClasses())
                 .filter(cls -> cls.toString().contains("net.minecraft"))
                 .findFirst()
                 .orElseThrow()
                 .getClassLoader();
+        String[] args = agentArgs.split(";");
+        Map<String, String> options = new HashMap<>();
+        for (int i = 1; i < args.length; i++) {
+            String[] option = args[i].split("=", 2);
+            options.put(option[0], option.length > 1 ? option[1] : "");
+        }
         version = MinecraftVersionResolver.resolveVersion();
-        mappingResolver = new YarnMappingResolver(version);
-        Native.load(agentArgs);
-        Native.init();
+        // The mappings are read natively
+        Native.load(args[0]);
+        mappingResolver = new YarnMappingResolver(version, options);
+        Thread listener = new Thread(Native::init, "mc-connect");
+        listener.setDaemon(true);
+        listener.start();
+        initialized = true;
     }
 }
\ No newline at end of file
@@ -2,38 +2,270 @@ package com.codemob.mcconnect;
 
 import org.jetbrains.annotations.Nullable;
 
+import java.lang.reflect.Constructor;
+import java.lang.reflect.Executable;
+import java.lang.reflect.Field;
+import java.lang.reflect.InvocationTargetException;
 import java.lang.reflect.Method;
+import java.util.ArrayDeque;
+import java.util.ArrayList;
+import java.util.Deque;
+import java.util.List;
+import java.util.concurrent.Executor;
 
+/**
+ * Reflection for native code. Names are in the namespace plugins are written against, except in
+ * the agent's own lookups, which use Yarn's names through {@link #yarn()} whatever that is.
+ */
 public class Tools {
     public static Class<?> loadClass(String className) throws ClassNotFoundException {
-        String mappedName = RustAgent.mappingResolver.getClassMapping(className).replace('/', '.');
+        return loadClass(RustAgent.mappingResolver, className);
+    }
+
+    private static Class<?> loadClass(YarnMappingResolver resolver, String className) throws ClassNotFoundException {
+        String mappedName = resolver.getClassMapping(className).replace('/', '.');
         return RustAgent.classLoader.loadClass(mappedName);
     }
+
     public static Object callMethod(String className, String methodName, String desc, @Nullable Object classInstance, Object... args) throws ReflectiveOperationException {
-        return loadClass(className).getMethod(
-                    RustAgent.mappingResolver.getMethodMapping(className, methodName, desc)
-                ).invoke(classInstance, args);
+        return callMethod(RustAgent.mappingResolver, className, methodName, desc, classInstance, args);
+    }
+
+    private static Object callMethod(YarnMappingResolver resolver, String className, String methodName, String desc, @Nullable Object classInstance, Object... args) throws ReflectiveOperationException {
+        Class<?> cls = loadClass(resolver, className);
+        Method method = findMethod(cls, getMethodName(resolver, cls, methodName, desc), getParameterTypes(resolver, desc));
+        return method.invoke(classInstance, args);
+    }
+
+    public static Object getField(String className, String fieldName, String desc, @Nullable Object classInstance) throws ReflectiveOperationException {
+        return getField(RustAgent.mappingResolver, className, fieldName, desc, classInstance);
+    }
+
+    private static Object getField(YarnMappingResolver resolver, String className, String fieldName, String desc, @Nullable Object classInstance) throws ReflectiveOperationException {
+        return findField(resolver, loadClass(resolver, className), fieldName, desc).get(classInstance);
+    }
+
+    public static void setField(String className, String fieldName, String desc, @Nullable Object classInstance, @Nullable Object value) throws ReflectiveOperationException {
+        YarnMappingResolver resolver = RustAgent.mappingResolver;
+        findField(resolver, loadClass(resolver, className), fieldName, desc).set(classInstance, value);
+    }
+
+    /**
+     * The mappings looked up by Yarn's names, for the agent's own lookups.
+     */
+    private static YarnMappingResolver yarn() {
+        return RustAgent.mappingResolver.yarnNames();
+    }
+
+    /**
+     * Looks up a method, or a constructor named {@code <init>}, for native code to get its JNI ID from.
+     */
+    public static Executable lookupMethod(String className, String methodName, String desc) throws ReflectiveOperationException {
+        YarnMappingResolver resolver = RustAgent.mappingResolver;
+        Class<?> cls = loadClass(resolver, className);
+        if (methodName.equals("<init>")) {
+            Constructor<?> constructor = cls.getDeclaredConstructor(getParameterTypes(resolver, desc));
+            constructor.trySetAccessible();
+            return constructor;
+        }
+        return findMethod(cls, getMethodName(resolver, cls, methodName, desc), getParameterTypes(resolver, desc));
     }
+
+    public static Field lookupField(String className, String fieldName, String desc) throws ReflectiveOperationException {
+        YarnMappingResolver resolver = RustAgent.mappingResolver;
+        return findField(resolver, loadClass(resolver, className), fieldName, desc);
+    }
+
+    /**
+     * Finds a field by its name in the resolver's namespace, which like a method may be declared by a
+     * superclass or interface.
+     */
+    private static Field findField(YarnMappingResolver resolver, Class<?> cls, String fieldName, String desc) throws NoSuchFieldException {
+        Deque<Class<?>> queue = new ArrayDeque<>(List.of(cls));
+        while (!queue.isEmpty()) {
+            Class<?> current = queue.poll();
+            String named = resolver.unmapClassName(current.getName()).replace('.', '/');
+            String mapped = resolver.findFieldMapping(named, fieldName, desc);
+            try {
+                Field field = current.getDeclaredField(mapped != null ? mapped : fieldName);
+                field.trySetAccessible();
+                return field;
+            } catch (NoSuchFieldException ignored) {
+            }
+            if (current.getSuperclass() != null) {
+                queue.add(current.getSuperclass());
+            }
+            queue.addAll(List.of(current.getInterfaces()));
+        }
+        throw new NoSuchFieldException(cls.getName() + "." + fieldName);
+    }
+
+    /**
+     * Maps a method name to intermediary, looking through superclasses and interfaces as the method may
+     * be inherited. Methods that have no mapping, such as those of the JDK, keep their name.
+     */
+    private static String getMethodName(YarnMappingResolver resolver, Class<?> cls, String methodName, String desc) {
+        Deque<Class<?>> queue = new ArrayDeque<>(List.of(cls));
+        while (!queue.isEmpty()) {
+            Class<?> current = queue.poll();
+            String named = resolver.unmapClassName(current.getName()).replace('.', '/');
+            String mapped = resolver.findMethodMapping(named, methodName, desc);
+            if (mapped != null) {
+                return mapped;
+            }
+            if (current.getSuperclass() != null) {
+                queue.add(current.getSuperclass());
+            }
+            queue.addAll(List.of(current.getInterfaces()));
+        }
+        return methodName;
+    }
+
+    private static Method findMethod(Class<?> cls, String name, Class<?>[] parameterTypes) throws NoSuchMethodException {
+        Method method;
+        try {
+            method = cls.getMethod(name, parameterTypes);
+        } catch (NoSuchMethodException e) {
+            method = findDeclaredMethod(cls, name, parameterTypes);
+            if (method == null) {
+                throw e;
+            }
+        }
+        // Public methods of package-private classes still need this to be called reflectively
+        method.trySetAccessible();
+        return method;
+    }
+
+    private static @Nullable Method findDeclaredMethod(Class<?> cls, String name, Class<?>[] parameterTypes) {
+        for (Class<?> current = cls; current != null; current = current.getSuperclass()) {
+            try {
+                return current.getDeclaredMethod(name, parameterTypes);
+            } catch (NoSuchMethodException ignored) {
+            }
+        }
+        return null;
+    }
+
+    public static Class<?>[] getParameterTypes(String desc) throws ClassNotFoundException {
+        return getParameterTypes(RustAgent.mappingResolver, desc);
+    }
+
+    private static Class<?>[] getParameterTypes(YarnMappingResolver resolver, String desc) throws ClassNotFoundException {
+        List<Class<?>> types = new ArrayList<>();
+        int start = 1;
+        while (desc.charAt(start) != ')') {
+            int end = start;
+            while (desc.charAt(end) == '[') {
+                end++;
+            }
+            end = desc.charAt(end) == 'L' ? desc.indexOf(';', end) + 1 : end + 1;
+            types.add(getType(resolver, desc.substring(start, end)));
+            start = end;
+        }
+        return types.toArray(new Class<?>[0]);
+    }
+
+    /**
+     * Loads the class of a field descriptor that uses names in the source namespace.
+     */
+    public static Class<?> getType(String desc) throws ClassNotFoundException {
+        return getType(RustAgent.mappingResolver, desc);
+    }
+
+    private static Class<?> getType(YarnMappingResolver resolver, String desc) throws ClassNotFoundException {
+        return switch (desc.charAt(0)) {
+            case 'Z' -> boolean.class;
+            case 'B' -> byte.class;
+            case 'C' -> char.class;
+            case 'S' -> short.class;
+            case 'I' -> int.class;
+            case 'J' -> long.class;
+            case 'F' -> float.class;
+            case 'D' -> double.class;
+            case 'V' -> void.class;
+            case 'L' -> loadClass(resolver, desc.substring(1, desc.length() - 1));
+            case '[' -> getType(resolver, desc.substring(1)).arrayType();
+            default -> throw new IllegalArgumentException("Invalid type descriptor: " + desc);
+        };
+    }
+
+    public static String getClassName(Object object) {
+        return RustAgent.mappingResolver.unmapClassName(object.getClass().getName()).replace('.', '/');
+    }
+
+    public static Object getEnumConstant(String className, String name) throws ReflectiveOperationException {
+        Class<?> cls = loadClass(className);
+        String mapped = RustAgent.mappingResolver.findFieldMapping(className, name, "L" + className + ";");
+        for (Object constant : cls.getEnumConstants()) {
+            if (((Enum<?>) constant).name().equals(mapped != null ? mapped : name)) {
+                return constant;
+            }
+        }
+        throw new IllegalArgumentException("No enum constant " + className + "." + name);
+    }
+
+    public static String getEnumClassName(Enum<?> constant) {
+        return RustAgent.mappingResolver.unmapClassName(constant.getDeclaringClass().getName()).replace('.', '/');
+    }
+
+    public static String getEnumName(Enum<?> constant) {
+        return RustAgent.mappingResolver.unmapFieldName(constant.getDeclaringClass().getName(), constant.name());
+    }
+
+    public static boolean isText(Object object) throws ClassNotFoundException {
+        return loadClass(yarn(), "net/minecraft/text/Text").isInstance(object);
+    }
+
+    public static String textToString(Object text) throws ReflectiveOperationException {
+        return (String) callMethod(yarn(), "net/minecraft/text/Text", "getString", "()Ljava/lang/String;", text);
+    }
+
+    public static Object literalText(String string) throws ReflectiveOperationException {
+        return callMethod(yarn(), "net/minecraft/text/Text", "literal", "(Ljava/lang/String;)Lnet/minecraft/text/MutableText;", null, string);
+    }
+
+    public static void execute(boolean onServer, Runnable task) throws ReflectiveOperationException {
+        YarnMappingResolver yarn = yarn();
+        Class<?> minecraftClient = loadClass(yarn, "net/minecraft/client/MinecraftClient");
+        Object executor = minecraftClient.getMethod(yarn.getMethodMapping(
+                "net/minecraft/client/MinecraftClient",
+                "getInstance",
+                "()Lnet/minecraft/client/MinecraftClient;")).invoke(null);
+        if (onServer) {
+            executor = minecraftClient.getMethod(yarn.getMethodMapping(
+                    "net/minecraft/client/MinecraftClient",
+                    "getServer",
+                    "()Lnet/minecraft/server/integrated/IntegratedServer;")).invoke(executor);
+            if (executor == null) {
+                throw new IllegalStateException("No integrated server is running");
+            }
+        }
+        // Both are ThreadExecutors, which run the task right away when already on their thread,
+        // unless it is running one of its tasks; the task is queued behind that one then.
+        ((Executor) executor).execute(task);
+    }
+
     public static void showToast(String title, String description) throws ReflectiveOperationException {
-        Class<?> minecraftClient = loadClass("net/minecraft/client/MinecraftClient");
-        Class<?> systemToast = loadClass("net/minecraft/client/toast/SystemToast");
-        Class<?> toastManager = loadClass("net/minecraft/client/toast/ToastManager");
-        Class<?> systemToast$type = loadClass("net/minecraft/client/toast/SystemToast$Type");
-        Class<?> text = loadClass("net/minecraft/text/Text");
+        YarnMappingResolver yarn = yarn();
+        Class<?> minecraftClient = loadClass(yarn, "net/minecraft/client/MinecraftClient");
+        Class<?> systemToast = loadClass(yarn, "net/minecraft/client/toast/SystemToast");
+        Class<?> toastManager = loadClass(yarn, "net/minecraft/client/toast/ToastManager");
+        Class<?> systemToast$type = loadClass(yarn, "net/minecraft/client/toast/SystemToast$Type");
+        Class<?> text = loadClass(yarn, "net/minecraft/text/Text");
 
 
-        Method text$of = text.getMethod(RustAgent.mappingResolver.getMethodMapping(
+        Method text$of = text.getMethod(yarn.getMethodMapping(
                 "net/minecraft/text/Text",
                 "of",
                 "(Ljava/lang/String;)Lnet/minecraft/text/Text;"
         ), String.class);
 
-        Object client = minecraftClient.getMethod(RustAgent.mappingResolver.getMethodMapping(
+        Object client = minecraftClient.getMethod(yarn.getMethodMapping(
                 "net/minecraft/client/MinecraftClient",
                 "getInstance",
                 "()Lnet/minecraft/client/MinecraftClient;")).invoke(null);
 
-        Object manager = minecraftClient.getMethod(RustAgent.mappingResolver.getMethodMapping(
+        Object manager = minecraftClient.getMethod(yarn.getMethodMapping(
                 "net/minecraft/client/MinecraftClient",
                 "getToastManager",
                 "()Lnet/minecraft/client/toast/ToastManager;")).invoke(client);
@@ -41,17 +273,50 @@ public class Tools {
         Object titleText = text$of.invoke(null, title);
         Object descriptionText = text$of.invoke(null, description);
 
-        Object textType = systemToast$type.getField(RustAgent.mappingResolver.getFieldMapping(
+        Object textType = getField(yarn,
                 "net/minecraft/client/toast/SystemToast$Type",
                 "PERIODIC_NOTIFICATION",
-                "Lnet/minecraft/client/toast/SystemToast$Type;"
-                )).get(null);
+                "Lnet/minecraft/client/toast/SystemToast$Type;",
+                null);
 
-        systemToast.getMethod(RustAgent.mappingResolver.getMethodMapping(
+        systemToast.getMethod(yarn.getMethodMapping(
                 "net/minecraft/client/toast/SystemToast",
                 "show",
                 "(Lnet/minecraft/client/toast/ToastManager;Lnet/minecraft/client/toast/SystemToast$Type;Lnet/minecraft/text/Text;Lnet/minecraft/text/Text;)V"),
                 toastManager, systemToast$type, text, text
                 ).invoke(null, manager, textType, titleText, descriptionText);
     }
+
+    public static Throwable unwrapException(Throwable throwable) {
+        while (throwable instanceof InvocationTargetException && throwable.getCause() != null) {
+            throwable = throwable.getCause();
+        }
+        return throwable;
+    }
+
+    public static String getExceptionClass(Throwable throwable) {
+        return RustAgent.mappingResolver.unmapClassName(throwable.getClass().getName());
+    }
+
+    public static String[] getStackTrace(Throwable throwable) {
+        List<String> lines = new ArrayList<>();
+        for (Throwable current = throwable; current != null; current = current.getCause()) {
+            if (current != throwable) {
+                lines.add("Caused by: " + getExceptionClass(current) + ": " + current.getMessage());
+            }
+            for (StackTraceElement element : current.getStackTrace()) {
+                String className = element.getClassName();
+                String location = element.getFileName() != null
+                        ? element.getFileName() + ":" + element.getLineNumber()
+                        : "Unknown Source";
+                lines.add("at " + RustAgent.mappingResolver.unmapClassName(className) + "."
+                        + RustAgent.mappingResolver.unmapMethodName(className, element.getMethodName())
+                        + "(" + location + ")");
+            }
+            if (current.getCause() == current) {
+                break;
+            }
+        }
+        return lines.toArray(new String[0]);
+    }
 }
@@ -1,11 +1,7 @@
 package com.codemob.mcconnect;
 
-import net.fabricmc.mappingio.MappingReader;
-import net.fabricmc.mappingio.tree.MappingTreeView;
-import net.fabricmc.mappingio.tree.MemoryMappingTree;
 import org.jetbrains.annotations.Nullable;
 import org.w3c.dom.Document;
-import org.w3c.dom.Node;
 import org.w3c.dom.NodeList;
 import org.xml.sax.SAXException;
 
@@ -16,23 +12,63 @@ import java.io.*;
 import java.net.URI;
 import java.net.URL;
 import java.net.URLConnection;
-import java.util.HashMap;
-import java.util.List;
+import java.nio.file.Files;
+import java.nio.file.StandardCopyOption;
+import java.util.Map;
 import java.util.Objects;
-import java.util.zip.GZIPInputStream;
 
+/**
+ * Maps names from the namespace plugins are written against, Yarn's {@code named} or Mojang's
+ * {@code mojmap}, to the one the game runs with, which is worked out from the classes it has
+ * unless given. This only finds the mappings, downloading Yarn's into the cache the first time;
+ * they are read and looked up natively.
+ */
 public class YarnMappingResolver {
-    private final MemoryMappingTree mappingTree = new MemoryMappingTree();
-    private final HashMap<String, Integer> namespaceMapping = new HashMap<>();
-
-    public YarnMappingResolver(String mcVersion) throws Exception {
-        String build = getLatestBuild(mcVersion);
-        File mappings = downloadMappings(build);
-        MappingReader.read(mappings.toPath(), mappingTree);
-        List<String> namespaces = mappingTree.getDstNamespaces();
-        for (int i = 0; i < namespaces.size(); i++) {
-            namespaceMapping.put(namespaces.get(i), i);
+    /**
+     * Whether names are looked up by their Yarn names rather than in the source namespace, read
+     * natively.
+     */
+    private final boolean yarn;
+    private final YarnMappingResolver yarnNames;
+
+    /**
+     * @param options the agent's options: {@code source} and {@code target} namespaces, and
+     *                {@code mappings} and {@code mojmap}, a file or directory of Yarn's and
+     *                Mojang's mappings used instead of the cache and the network
+     */
+    public YarnMappingResolver(String mcVersion, Map<String, String> options) throws Exception {
+        this.yarn = false;
+        this.yarnNames = new YarnMappingResolver();
+        String source = options.getOrDefault("source", "named");
+        @Nullable String target = options.get("target");
+
+        // Yarn's mappings are needed whatever the source namespace, for the agent's own lookups
+        String yarn = findMappings(mcVersion, "yarn", options.get("mappings"));
+        if (yarn == null) {
+            yarn = cachePath(mcVersion, "yarn");
+            downloadMappings(getLatestBuild(mcVersion), new File(yarn));
         }
+        String mojmap = null;
+        if (source.equals("mojmap")) {
+            mojmap = findMappings(mcVersion, "mojmap", options.get("mojmap"));
+            if (mojmap == null) {
+                throw new FileNotFoundException("no Mojang mappings for " + mcVersion + " in the cache");
+            }
+        }
+        load(yarn, mojmap, source, target);
+    }
+
+    private YarnMappingResolver() {
+        this.yarn = true;
+        this.yarnNames = this;
+    }
+
+    /**
+     * The same mappings looked up by Yarn's names, which the agent's own lookups use whatever
+     * namespace plugins are written against.
+     */
+    public YarnMappingResolver yarnNames() {
+        return yarnNames;
     }
 
     private String getLatestBuild(String version) throws IOException, SAXException, ParserConfigurationException {
@@ -59,46 +95,52 @@ public class YarnMappingResolver {
                 }
             }
         }
+        if (latestVersion == null) {
+            throw new FileNotFoundException("no Yarn mappings for " + version);
+        }
         return latestVersion;
     }
 
-    private File downloadMappings(String build) throws IOException {
+    private void downloadMappings(String build, File path) throws IOException {
         String downloadUrl = "https://maven.fabricmc.net/net/fabricmc/yarn/" + build + "/yarn-" + build + "-tiny.gz";
-        File tmpFile = File.createTempFile("yarn-mappings", ".tiny");
+        // Download next to the cached file first so a failed download isn't cached
+        File tmpFile = File.createTempFile("yarn-mappings", ".part", path.getParentFile());
         tmpFile.deleteOnExit();
 
         URLConnection conn = URI.create(downloadUrl).toURL().openConnection();
         try (
-                InputStream in = new GZIPInputStream(conn.getInputStream());
+                InputStream in = conn.getInputStream();
                 FileOutputStream out = new FileOutputStream(tmpFile)
         ) {
             in.transferTo(out);
         }
 
-        return tmpFile;
+        Files.move(tmpFile.toPath(), path.toPath(), StandardCopyOption.REPLACE_EXISTING);
     }
 
-    public String getClassMapping(String className) {
-        return mappingTree.mapClassName(
-                className,
-                namespaceMapping.get("named"),
-                namespaceMapping.get("intermediary")
-        );
-    }
+    private static native @Nullable String findMappings(String mcVersion, String kind, @Nullable String local) throws IOException;
+
+    private static native String cachePath(String mcVersion, String kind) throws IOException;
+
+    private static native void load(String yarn, @Nullable String mojmap, String source, @Nullable String target) throws IOException;
+
+    public native String getClassMapping(String className);
 
     public String getMethodMapping(String className, String methodName, @Nullable String desc) {
-        return Objects.requireNonNull(mappingTree.getMethod(
-                        className, methodName, desc, namespaceMapping.get("named")))
-                .getDstName(namespaceMapping.get("intermediary"));
+        return Objects.requireNonNull(findMethodMapping(className, methodName, desc));
     }
 
+    public native @Nullable String findMethodMapping(String className, String methodName, @Nullable String desc);
+
+    public native @Nullable String findFieldMapping(String className, String fieldName, @Nullable String desc);
+
     public String getFieldMapping(String className, String fieldName, @Nullable String desc) {
-        return Objects.requireNonNull(mappingTree.getField(
-                        className, fieldName, desc, namespaceMapping.get("named")))
-                .getDstName(namespaceMapping.get("intermediary"));
+        return Objects.requireNonNull(findFieldMapping(className, fieldName, desc));
     }
 
-    public MemoryMappingTree getMappingTree() {
-        return mappingTree;
-    }
+    public native String unmapClassName(String className);
+
+    public native String unmapMethodName(String className, String methodName);
+
+    public native String unmapFieldName(String className, String fieldName);
 }
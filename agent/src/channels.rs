@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex};
+
+use jni::JavaVM;
+use libloading::Library;
+use mc_connect::communication::{CustomPacket, ErrorKind, ErrorPacket, Packet};
+use mc_connect::panic;
+use mc_connect::plugin::{
+    ChannelHandler, ChannelRegistrar, PluginSlice, REGISTER_SYMBOL, RegisterFn,
+};
+
+/// Handlers plugins have registered for custom packet channels.
+#[derive(Default)]
+pub struct ChannelRegistry {
+    handlers: Mutex<HashMap<String, Handler>>,
+}
+
+struct Handler {
+    plugin: String,
+    handler: ChannelHandler,
+    /// Keeps the plugin loaded while the handler runs, even if it is being unloaded.
+    library: Arc<Library>,
+}
+
+impl ChannelRegistry {
+    /// Lets the library of `plugin` register its channel handlers, if it exports a registration
+    /// function, returning the channels it registered.
+    ///
+    /// The handlers replace those `plugin` registered before. Nothing is registered if another
+    /// plugin has registered one of the channels.
+    pub fn register_library(
+        &self,
+        plugin: &str,
+        library: &Arc<Library>,
+    ) -> Result<Vec<String>, String> {
+        let mut handlers = HashMap::new();
+        if let Ok(register) = unsafe { library.get::<RegisterFn>(REGISTER_SYMBOL.as_bytes()) } {
+            let mut registrar = unsafe {
+                ChannelRegistrar::new(
+                    &mut handlers as *mut HashMap<String, ChannelHandler> as *mut c_void,
+                    collect_handler,
+                )
+            };
+            register(&mut registrar);
+        }
+
+        let mut channels = handlers.keys().cloned().collect::<Vec<_>>();
+        channels.sort_unstable();
+        let mut registered = self.handlers.lock().unwrap();
+        for channel in &channels {
+            if let Some(owner) = registered
+                .get(channel)
+                .filter(|handler| handler.plugin != plugin)
+            {
+                return Err(format!(
+                    "channel {} is already registered by plugin {}",
+                    channel, owner.plugin
+                ));
+            }
+        }
+        registered.retain(|_, handler| handler.plugin != plugin);
+        registered.extend(handlers.into_iter().map(|(channel, handler)| {
+            let handler = Handler {
+                plugin: plugin.to_string(),
+                handler,
+                library: Arc::clone(library),
+            };
+            (channel, handler)
+        }));
+        Ok(channels)
+    }
+
+    /// Removes the handlers `plugin` registered.
+    pub fn unregister(&self, plugin: &str) {
+        self.handlers
+            .lock()
+            .unwrap()
+            .retain(|_, handler| handler.plugin != plugin);
+    }
+
+    pub fn dispatch(&self, vm: &JavaVM, packet: CustomPacket) -> Packet {
+        let handler = self
+            .handlers
+            .lock()
+            .unwrap()
+            .get(&packet.channel)
+            .map(|handler| (handler.handler, Arc::clone(&handler.library)));
+        let Some((handler, _library)) = handler else {
+            return ErrorPacket::new(
+                ErrorKind::UnknownChannel,
+                format!("no handler registered for channel {}", packet.channel),
+            );
+        };
+        match handler(vm, packet.payload.as_slice().into()).into_result() {
+            Ok(payload) => CustomPacket::new(packet.channel, payload),
+            Err(message) => ErrorPacket::new(ErrorKind::Plugin, message),
+        }
+    }
+}
+
+extern "C" fn collect_handler(context: *mut c_void, channel: PluginSlice, handler: ChannelHandler) {
+    let collected = panic::catch(|| {
+        let handlers = unsafe { &mut *(context as *mut HashMap<String, ChannelHandler>) };
+        let channel = String::from_utf8_lossy(channel.as_slice()).into_owned();
+        handlers.insert(channel, handler);
+    });
+    if let Err(panic) = collected {
+        eprintln!("Failed to register a channel handler: {}", panic);
+    }
+}
@@ -0,0 +1,98 @@
+use jni::JNIEnv;
+use jni::objects::{JObject, JObjectArray, JString, JThrowable, JValue};
+use mc_connect::communication::{ErrorKind, ErrorPacket, Packet};
+use mc_connect::panic;
+
+pub const TOOLS_CLASS: &str = "com/codemob/mcconnect/Tools";
+
+/// Converts a JNI error into an error packet, clearing any pending Java exception.
+pub fn java_error(env: &mut JNIEnv, error: jni::errors::Error) -> Packet {
+    if !env.exception_check().unwrap_or(false) {
+        return ErrorPacket::new(ErrorKind::Jni, error.to_string());
+    }
+    let Ok(throwable) = env.exception_occurred() else {
+        return ErrorPacket::new(ErrorKind::Jni, error.to_string());
+    };
+    let _ = env.exception_clear();
+
+    describe_exception(env, &throwable).unwrap_or_else(|e| {
+        let _ = env.exception_clear();
+        ErrorPacket::new(
+            ErrorKind::JavaException,
+            format!("failed to describe java exception: {}", e),
+        )
+    })
+}
+
+/// Runs the body of a native method, throwing a panic as a `RuntimeException` rather than
+/// letting it unwind into the JVM, and answering `fallback` then.
+pub fn throw_panics<F, R>(env: &mut JNIEnv, fallback: R, body: F) -> R
+where
+    F: FnOnce(&mut JNIEnv) -> R,
+{
+    panic::catch(|| body(env)).unwrap_or_else(|panic| {
+        // The panic replaces whatever the body threw before it
+        let _ = env.exception_clear();
+        let _ = env.throw_new("java/lang/RuntimeException", panic.with_backtrace());
+        fallback
+    })
+}
+
+fn describe_exception(env: &mut JNIEnv, throwable: &JThrowable) -> jni::errors::Result<Packet> {
+    let tools_class = env.find_class(TOOLS_CLASS)?;
+    let throwable = env
+        .call_static_method(
+            &tools_class,
+            "unwrapException",
+            "(Ljava/lang/Throwable;)Ljava/lang/Throwable;",
+            &[JValue::Object(throwable)],
+        )?
+        .l()?;
+
+    let exception_class = env
+        .call_static_method(
+            &tools_class,
+            "getExceptionClass",
+            "(Ljava/lang/Throwable;)Ljava/lang/String;",
+            &[JValue::Object(&throwable)],
+        )?
+        .l()?;
+    let exception_class = get_string(env, exception_class)?;
+
+    let message = env
+        .call_method(&throwable, "getMessage", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let message = if message.is_null() {
+        exception_class.clone()
+    } else {
+        get_string(env, message)?
+    };
+
+    let stack_trace: JObjectArray = env
+        .call_static_method(
+            &tools_class,
+            "getStackTrace",
+            "(Ljava/lang/Throwable;)[Ljava/lang/String;",
+            &[JValue::Object(&throwable)],
+        )?
+        .l()?
+        .into();
+    let length = env.get_array_length(&stack_trace)?;
+    let stack_trace = (0..length)
+        .map(|i| {
+            let line = env.get_object_array_element(&stack_trace, i)?;
+            get_string(env, line)
+        })
+        .collect::<jni::errors::Result<_>>()?;
+
+    Ok(Packet::Err(ErrorPacket {
+        kind: ErrorKind::JavaException,
+        message,
+        exception_class: Some(exception_class),
+        stack_trace,
+    }))
+}
+
+fn get_string(env: &mut JNIEnv, string: JObject) -> jni::errors::Result<String> {
+    Ok(env.get_string(&JString::from(string))?.into())
+}
@@ -0,0 +1,85 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use mc_connect::communication::transfer::{
+    AGENT_FILES_DIR, CHUNK_SIZE, checksum, checksum_file, validate_relative_path,
+};
+use mc_connect::communication::{
+    DownloadPacket, ErrorKind, ErrorPacket, FileChunkPacket, FileInfoPacket, Packet, UploadPacket,
+};
+
+pub fn upload(packet: UploadPacket) -> Packet {
+    write_chunk(packet).unwrap_or_else(|e| ErrorPacket::new(ErrorKind::Io, e.to_string()))
+}
+
+pub fn download(packet: DownloadPacket) -> Packet {
+    read_chunk(packet).unwrap_or_else(|e| ErrorPacket::new(ErrorKind::Io, e.to_string()))
+}
+
+fn write_chunk(packet: UploadPacket) -> io::Result<Packet> {
+    let path = env::current_dir()?
+        .join(AGENT_FILES_DIR)
+        .join(validate_relative_path(&packet.path)?);
+    let mut part_path = path.clone().into_os_string();
+    part_path.push(".part");
+    let part_path = PathBuf::from(part_path);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = if packet.offset == 0 {
+        File::create(&part_path)?
+    } else {
+        OpenOptions::new().append(true).open(&part_path)?
+    };
+    let len = file.metadata()?.len();
+    if len != packet.offset {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected chunk at offset {}, got {}", len, packet.offset),
+        ));
+    }
+    file.write_all(&packet.data)?;
+    drop(file);
+
+    let Some(expected) = packet.checksum else {
+        return Ok(Packet::Confirmation);
+    };
+    let (size, actual) = checksum_file(&part_path)?;
+    if actual != expected {
+        fs::remove_file(&part_path)?;
+        return Ok(ErrorPacket::new(
+            ErrorKind::ChecksumMismatch,
+            format!("checksum mismatch uploading {}", packet.path.display()),
+        ));
+    }
+    fs::rename(&part_path, &path)?;
+    Ok(Packet::File(FileInfoPacket {
+        path,
+        size,
+        checksum: actual,
+    }))
+}
+
+fn read_chunk(packet: DownloadPacket) -> io::Result<Packet> {
+    let path = env::current_dir()?.join(validate_relative_path(&packet.path)?);
+    let mut file = File::open(&path)?;
+    let size = file.metadata()?.len();
+    let checksum = if packet.offset == 0 {
+        Some(checksum(&mut file, size)?)
+    } else {
+        None
+    };
+
+    file.seek(SeekFrom::Start(packet.offset))?;
+    let mut data = Vec::new();
+    file.take(packet.length.min(CHUNK_SIZE as u64))
+        .read_to_end(&mut data)?;
+    Ok(Packet::FileChunk(FileChunkPacket {
+        data,
+        size,
+        checksum,
+    }))
+}
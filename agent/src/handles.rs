@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use jni::objects::GlobalRef;
+use mc_connect::communication::value::ObjectHandle;
+
+/// Objects a session holds handles to, kept alive until released or the session closes.
+#[derive(Default)]
+pub struct HandleTable {
+    num_handles: AtomicU64,
+    objects: Mutex<HashMap<ObjectHandle, GlobalRef>>,
+}
+
+impl HandleTable {
+    pub fn insert(&self, object: GlobalRef) -> ObjectHandle {
+        let handle = ObjectHandle(self.num_handles.fetch_add(1, Ordering::Relaxed) + 1);
+        self.objects.lock().unwrap().insert(handle, object);
+        handle
+    }
+
+    pub fn get(&self, handle: ObjectHandle) -> Option<GlobalRef> {
+        self.objects.lock().unwrap().get(&handle).cloned()
+    }
+
+    /// Drops the agent's reference to an object, returning whether the handle existed.
+    pub fn release(&self, handle: ObjectHandle) -> bool {
+        self.objects.lock().unwrap().remove(&handle).is_some()
+    }
+}
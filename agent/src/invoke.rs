@@ -0,0 +1,135 @@
+use jni::JNIEnv;
+use jni::objects::JObject;
+use mc_connect::communication::value::JavaType;
+use mc_connect::communication::{
+    GetFieldPacket, InvokePacket, InvokeTarget, Packet, SetFieldPacket,
+};
+
+use crate::exception::TOOLS_CLASS;
+use crate::handles::HandleTable;
+use crate::value::{self, ValueError};
+
+pub fn get_field(env: &mut JNIEnv, handles: &HandleTable, packet: GetFieldPacket) -> Packet {
+    let result = env.with_local_frame(16, |env| {
+        let instance = target(env, handles, packet.target)?;
+        let class_name = env.new_string(&packet.class_name)?;
+        let field_name = env.new_string(&packet.field_name)?;
+        let desc = env.new_string(&packet.desc)?;
+        let result = env
+            .call_static_method(
+                TOOLS_CLASS,
+                "getField",
+                "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/Object;)Ljava/lang/Object;",
+                &[
+                    (&class_name).into(),
+                    (&field_name).into(),
+                    (&desc).into(),
+                    (&instance).into(),
+                ],
+            )?
+            .l()?;
+        value::from_object(env, handles, &result).map_err(ValueError::from)
+    });
+    match result {
+        Ok(value) => Packet::Value(value),
+        Err(e) => e.into_packet(env),
+    }
+}
+
+pub fn set_field(env: &mut JNIEnv, handles: &HandleTable, packet: SetFieldPacket) -> Packet {
+    let result = env.with_local_frame(16, |env| -> Result<(), ValueError> {
+        let ty = JavaType::parse(&packet.desc)?;
+        let value = value::to_object(env, handles, packet.value, &ty)?;
+        let instance = target(env, handles, packet.target)?;
+        let class_name = env.new_string(&packet.class_name)?;
+        let field_name = env.new_string(&packet.field_name)?;
+        let desc = env.new_string(&packet.desc)?;
+        env.call_static_method(
+            TOOLS_CLASS,
+            "setField",
+            "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/Object;Ljava/lang/Object;)V",
+            &[
+                (&class_name).into(),
+                (&field_name).into(),
+                (&desc).into(),
+                (&instance).into(),
+                (&value).into(),
+            ],
+        )?;
+        Ok(())
+    });
+    match result {
+        Ok(()) => Packet::Confirmation,
+        Err(e) => e.into_packet(env),
+    }
+}
+
+pub fn invoke(env: &mut JNIEnv, handles: &HandleTable, packet: InvokePacket) -> Packet {
+    let result = env.with_local_frame(16, |env| {
+        let result = call(env, handles, packet)?;
+        value::from_object(env, handles, &result).map_err(ValueError::from)
+    });
+    match result {
+        Ok(value) => Packet::Value(value),
+        Err(e) => e.into_packet(env),
+    }
+}
+
+fn call<'local>(
+    env: &mut JNIEnv<'local>,
+    handles: &HandleTable,
+    packet: InvokePacket,
+) -> Result<JObject<'local>, ValueError> {
+    let (params, _) = JavaType::parse_method(&packet.desc)?;
+    if params.len() != packet.args.len() {
+        return Err(ValueError::InvalidArgument(format!(
+            "{} takes {} arguments, {} given",
+            packet.method_name,
+            params.len(),
+            packet.args.len()
+        )));
+    }
+
+    let instance = target(env, handles, packet.target)?;
+
+    let args = env.new_object_array(params.len() as i32, "java/lang/Object", JObject::null())?;
+    for (i, (arg, param)) in packet.args.into_iter().zip(&params).enumerate() {
+        let arg = value::to_object(env, handles, arg, param)?;
+        env.set_object_array_element(&args, i as i32, arg)?;
+    }
+    let class_name = env.new_string(&packet.class_name)?;
+    let method_name = env.new_string(&packet.method_name)?;
+    let desc = env.new_string(&packet.desc)?;
+    Ok(env
+        .call_static_method(
+            TOOLS_CLASS,
+            "callMethod",
+            "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/Object;",
+            &[
+                (&class_name).into(),
+                (&method_name).into(),
+                (&desc).into(),
+                (&instance).into(),
+                (&args).into(),
+            ],
+        )?
+        .l()?)
+}
+
+/// Resolves the instance a method or field is accessed on, which is null for static ones.
+fn target<'local>(
+    env: &mut JNIEnv<'local>,
+    handles: &HandleTable,
+    target: InvokeTarget,
+) -> Result<JObject<'local>, ValueError> {
+    Ok(match target {
+        InvokeTarget::Static => JObject::null(),
+        InvokeTarget::Invoke(packet) => call(env, handles, *packet)?,
+        InvokeTarget::Object(handle) => {
+            let object = handles.get(handle).ok_or_else(|| {
+                ValueError::InvalidArgument(format!("unknown handle {}", handle.0))
+            })?;
+            env.new_local_ref(object)?
+        }
+    })
+}
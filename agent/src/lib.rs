@@ -1,69 +1,420 @@
-use libloading::{Library, Symbol};
+mod channels;
+mod exception;
+mod files;
+mod handles;
+mod invoke;
+mod mappings;
+mod plugins;
+mod session;
+mod value;
+
+use std::ffi::c_void;
+use std::sync::Arc;
+use std::time::Duration;
+
+use channels::ChannelRegistry;
+use exception::{TOOLS_CLASS, java_error, throw_panics};
 use mc_connect::communication::server::ServerPacketManager;
-use mc_connect::communication::{ADDRESS, Packet};
+use mc_connect::communication::{
+    ADDRESS, ErrorKind, ErrorPacket, GameThread, Packet, ReturnPacket, RunPacket, SchedulePacket,
+    ToastPacket,
+};
+use mc_connect::game_thread::{self, TaskFn};
+use mc_connect::panic;
 
 use jni::objects::JClass;
+use jni::sys::jlong;
 use jni::{JNIEnv, JavaVM};
-use tokio::net::TcpListener;
+use plugins::PluginRegistry;
+use session::{Session, SessionRegistry};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Handle;
+
+/// How long a packet handled on a game thread waits for it, shorter than a client's default
+/// timeout so the client learns why.
+const GAME_THREAD_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// State shared by every session.
+struct Agent {
+    vm: JavaVM,
+    sessions: SessionRegistry,
+    channels: ChannelRegistry,
+    plugins: PluginRegistry,
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_Native_init(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+) {
+    throw_panics(&mut env, (), listen);
+}
 
-#[tokio::main]
 #[unsafe(no_mangle)]
-pub async extern "system" fn Java_com_codemob_mcconnect_Native_init(
-    env: JNIEnv<'_>,
+pub extern "system" fn Java_com_codemob_mcconnect_NativeTask_runNative(
+    mut env: JNIEnv<'_>,
     _class: JClass<'_>,
+    function: jlong,
+    data: jlong,
 ) {
-    let vm: JavaVM = env.get_java_vm().unwrap();
+    throw_panics(&mut env, (), |env| {
+        // SAFETY: a `NativeTask` is only ever created by `game_thread::execute` with a `TaskFn`
+        let function: TaskFn = unsafe { std::mem::transmute(function as usize) };
+        unsafe { function(data as usize as *mut c_void, env.get_raw()) };
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_NativeTask_dropNative(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    function: jlong,
+    data: jlong,
+) {
+    throw_panics(&mut env, (), |_| {
+        // SAFETY: a `NativeTask` is only ever created by `game_thread::execute` with a `TaskFn`
+        let function: TaskFn = unsafe { std::mem::transmute(function as usize) };
+        unsafe { function(data as usize as *mut c_void, std::ptr::null_mut()) };
+    });
+}
+
+#[tokio::main]
+async fn listen(env: &mut JNIEnv<'_>) {
+    let agent = Arc::new(Agent {
+        vm: env.get_java_vm().unwrap(),
+        sessions: SessionRegistry::default(),
+        channels: ChannelRegistry::default(),
+        plugins: PluginRegistry::default(),
+    });
     let _ = tokio::spawn(async move {
         let listener = TcpListener::bind(ADDRESS)
             .await
             .expect("Failed to bind TCP listener");
         println!("Listening on {}", ADDRESS);
-        let (socket, addr) = listener
-            .accept()
-            .await
-            .expect("Failed to accept connection");
-
-        println!("Accepted connection from {}", addr);
-        let packet_manager = ServerPacketManager::new(socket);
-        packet_manager
-            .start_listening(move |packet| {
-                let mut env = vm.attach_current_thread_as_daemon().unwrap();
-                let tools_class = env.find_class("com/codemob/mcconnect/Tools").unwrap();
-
-                match packet {
-                    Packet::Print(print_packet) => {
-                        print!("{}", print_packet.message);
-                        Packet::Confirmation
-                    }
-                    Packet::Toast(toast_packet) => {
-                        env.call_static_method(
-                            tools_class,
-                            "showToast",
-                            "(Ljava/lang/String;Ljava/lang/String;)V",
-                            &[
-                                (&env.new_string(toast_packet.title).unwrap()).into(),
-                                (&env.new_string(toast_packet.body).unwrap()).into(),
-                            ],
-                        )
-                        .unwrap();
-                        Packet::Confirmation
-                    }
-                    Packet::Run(packet) => unsafe {
-                        let lib = Library::new(packet.lib).unwrap();
-                        let func: Symbol<unsafe extern "C" fn(&JavaVM)> =
-                            lib.get(packet.func.as_bytes()).unwrap();
-                        func(&vm);
-                        Packet::Confirmation
-                    },
-                    packet => {
-                        eprintln!("Invalid packet recieved: {:?}", packet);
-                        Packet::Err
-                    }
+        loop {
+            let (socket, addr) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {}", e);
+                    continue;
                 }
-            })
-            .await
-            .unwrap();
-        println!("Connection closed.");
+            };
+            println!("Accepted connection from {}", addr);
+            tokio::spawn(serve(Arc::clone(&agent), socket));
+        }
     })
     .await;
 }
+
+async fn serve(agent: Arc<Agent>, socket: TcpStream) {
+    // The mappings are loaded before the agent starts listening
+    let namespace = mappings::runtime_namespace().unwrap_or(mc_connect::mappings::INTERMEDIARY);
+    let packet_manager = match ServerPacketManager::new(socket, namespace).await {
+        Ok(packet_manager) => packet_manager,
+        Err(e) => {
+            eprintln!("Handshake failed: {}", e);
+            return;
+        }
+    };
+    let (session, mut events) = agent.sessions.open();
+
+    let event_sender = packet_manager.clone();
+    let forward_events = tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if event_sender.send_packet(event).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let handler_session = Arc::clone(&session);
+    let handler_agent = Arc::clone(&agent);
+    let _ = packet_manager
+        .start_listening(move |packet| {
+            // A panic answers the packet that caused it, and the session carries on
+            panic::catch(|| {
+                handler_agent
+                    .sessions
+                    .handle(&handler_session, packet)
+                    .unwrap_or_else(|packet| {
+                        handle_packet(&handler_agent, &handler_session, packet, None)
+                    })
+            })
+            .unwrap_or_else(ErrorPacket::panic)
+        })
+        .await;
+
+    forward_events.abort();
+    agent.sessions.close(&session);
+    println!("Session {} closed.", session.id);
+}
+
+/// Answers `packet`, on the game thread it was scheduled on if any.
+fn handle_packet(
+    agent: &Arc<Agent>,
+    session: &Arc<Session>,
+    packet: Packet,
+    current: Option<GameThread>,
+) -> Packet {
+    let mut env = match agent.vm.attach_current_thread_as_daemon() {
+        Ok(env) => env,
+        Err(e) => return ErrorPacket::new(ErrorKind::Jni, e.to_string()),
+    };
+
+    match packet {
+        Packet::Print(print_packet) => {
+            print!("{}", print_packet.message);
+            Packet::Confirmation
+        }
+        Packet::Toast(toast_packet) => on_game_thread(
+            &mut env,
+            current,
+            GameThread::Client,
+            move |env| match show_toast(env, toast_packet) {
+                Ok(()) => Packet::Confirmation,
+                Err(e) => java_error(env, e),
+            },
+        ),
+        Packet::Schedule(schedule_packet) => {
+            schedule(agent, session, &mut env, current, schedule_packet)
+        }
+        Packet::Run(run_packet) => run_library(agent, run_packet),
+        Packet::Load(load_packet) => plugin_response(
+            agent
+                .plugins
+                .load(&agent.channels, &load_packet.lib)
+                .map(Packet::Plugin),
+        ),
+        Packet::Call(call_packet) => plugin_response(
+            agent
+                .plugins
+                .call(
+                    &agent.vm,
+                    &call_packet.plugin,
+                    &call_packet.entry,
+                    &call_packet.payload,
+                )
+                .map(|payload| Packet::Return(ReturnPacket { payload })),
+        ),
+        Packet::Unload(name) => plugin_response(
+            agent
+                .plugins
+                .unload(&agent.vm, &agent.channels, &name)
+                .map(|()| Packet::Confirmation),
+        ),
+        Packet::Reload(name) => plugin_response(
+            agent
+                .plugins
+                .reload(&agent.vm, &agent.channels, &name)
+                .map(Packet::Plugin),
+        ),
+        Packet::Plugins => Packet::PluginList(agent.plugins.list()),
+        Packet::Invoke(invoke_packet) => invoke::invoke(&mut env, &session.handles, invoke_packet),
+        Packet::GetField(get_field_packet) => {
+            invoke::get_field(&mut env, &session.handles, get_field_packet)
+        }
+        Packet::SetField(set_field_packet) => {
+            invoke::set_field(&mut env, &session.handles, *set_field_packet)
+        }
+        Packet::Release(handle) => {
+            if session.handles.release(handle) {
+                Packet::Confirmation
+            } else {
+                ErrorPacket::new(
+                    ErrorKind::InvalidArgument,
+                    format!("unknown handle {}", handle.0),
+                )
+            }
+        }
+        Packet::Custom(custom_packet) => agent.channels.dispatch(&agent.vm, custom_packet),
+        Packet::Upload(upload_packet) => files::upload(upload_packet),
+        Packet::Download(download_packet) => files::download(download_packet),
+        packet => {
+            eprintln!("Invalid packet recieved: {:?}", packet);
+            ErrorPacket::new(
+                ErrorKind::UnsupportedPacket,
+                format!("unsupported packet: {:?}", packet),
+            )
+        }
+    }
+}
+
+/// Runs `handler` on a game thread and waits for the packet it answers with.
+///
+/// A packet handled on `current` runs its handler right away if that is `thread`, since the
+/// game would queue the task behind the one running. It can't wait for the other game thread,
+/// which may itself be waiting for `current`.
+fn on_game_thread<F>(
+    env: &mut JNIEnv,
+    current: Option<GameThread>,
+    thread: GameThread,
+    handler: F,
+) -> Packet
+where
+    F: FnOnce(&mut JNIEnv) -> Packet + Send + 'static,
+{
+    match current {
+        Some(current) if current == thread => return handler(env),
+        Some(current) => {
+            return ErrorPacket::new(
+                ErrorKind::UnsupportedPacket,
+                format!(
+                    "the {:?} thread can't wait for the {:?} thread",
+                    current, thread
+                ),
+            );
+        }
+        None => {}
+    }
+    // The task's own guard can only log a panic, so it is answered here
+    let handler =
+        move |env: &mut JNIEnv| panic::catch(|| handler(env)).unwrap_or_else(ErrorPacket::panic);
+    let response = match game_thread::execute(env, thread, handler) {
+        Ok(response) => response,
+        Err(e) => return java_error(env, e),
+    };
+    let response = tokio::task::block_in_place(|| {
+        Handle::current().block_on(tokio::time::timeout(GAME_THREAD_TIMEOUT, response))
+    });
+    match response {
+        Ok(Ok(packet)) => packet,
+        Ok(Err(_)) => ErrorPacket::new(
+            ErrorKind::Jni,
+            format!("{:?} thread dropped the task", thread),
+        ),
+        Err(_) => ErrorPacket::new(
+            ErrorKind::Timeout,
+            format!(
+                "{:?} thread didn't run the task within {:?}",
+                thread, GAME_THREAD_TIMEOUT
+            ),
+        ),
+    }
+}
+
+fn schedule(
+    agent: &Arc<Agent>,
+    session: &Arc<Session>,
+    env: &mut JNIEnv,
+    current: Option<GameThread>,
+    packet: SchedulePacket,
+) -> Packet {
+    let agent = Arc::clone(agent);
+    let session = Arc::clone(session);
+    let thread = packet.thread;
+    on_game_thread(env, current, thread, move |_| {
+        handle_packet(&agent, &session, *packet.packet, Some(thread))
+    })
+}
+
+fn show_toast(env: &mut JNIEnv, toast_packet: ToastPacket) -> jni::errors::Result<()> {
+    let tools_class = env.find_class(TOOLS_CLASS)?;
+    let title = env.new_string(toast_packet.title)?;
+    let body = env.new_string(toast_packet.body)?;
+    env.call_static_method(
+        tools_class,
+        "showToast",
+        "(Ljava/lang/String;Ljava/lang/String;)V",
+        &[(&title).into(), (&body).into()],
+    )?;
+    Ok(())
+}
+
+fn run_library(agent: &Agent, packet: RunPacket) -> Packet {
+    let result = agent
+        .plugins
+        .load_or_reload(&agent.vm, &agent.channels, &packet.lib)
+        .and_then(|info| {
+            agent
+                .plugins
+                .call(&agent.vm, &info.name, &packet.func, &packet.payload)
+        });
+    plugin_response(result.map(|payload| Packet::Return(ReturnPacket { payload })))
+}
+
+fn plugin_response(result: Result<Packet, String>) -> Packet {
+    result.unwrap_or_else(|message| ErrorPacket::new(ErrorKind::Plugin, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use jni::InitArgsBuilder;
+    use mc_connect::communication::CallPacket;
+    use mc_connect::plugin::{ON_UNLOAD_SYMBOL, REGISTER_SYMBOL};
+
+    use super::*;
+
+    fn agent() -> Arc<Agent> {
+        static VM: OnceLock<JavaVM> = OnceLock::new();
+        let vm = VM.get_or_init(|| JavaVM::new(InitArgsBuilder::new().build().unwrap()).unwrap());
+        Arc::new(Agent {
+            vm: vm.attach_current_thread().unwrap().get_java_vm().unwrap(),
+            sessions: SessionRegistry::default(),
+            channels: ChannelRegistry::default(),
+            plugins: PluginRegistry::default(),
+        })
+    }
+
+    fn error(packet: Packet) -> ErrorPacket {
+        match packet {
+            Packet::Err(error) => error,
+            packet => panic!("expected an error, got {:?}", packet),
+        }
+    }
+
+    fn error_kind(packet: Packet) -> ErrorKind {
+        error(packet).kind
+    }
+
+    #[test]
+    fn handler_runs_right_away_on_its_own_thread() {
+        let agent = agent();
+        let mut env = agent.vm.attach_current_thread().unwrap();
+        let response = on_game_thread(
+            &mut env,
+            Some(GameThread::Client),
+            GameThread::Client,
+            |_| Packet::Confirmation,
+        );
+        assert_eq!(response, Packet::Confirmation);
+    }
+
+    #[test]
+    fn scheduled_toast_runs_on_the_client_thread() {
+        let agent = agent();
+        let (session, _events) = agent.sessions.open();
+        let toast = ToastPacket::new("title".to_string(), "body".to_string());
+        let schedule = SchedulePacket::new(GameThread::Client, toast);
+        // The game's classes aren't there to show it, but it doesn't wait for the thread it
+        // is running on
+        let response = handle_packet(&agent, &session, schedule, Some(GameThread::Client));
+        assert_eq!(error_kind(response), ErrorKind::JavaException);
+    }
+
+    #[test]
+    fn scheduled_toast_fails_on_the_server_thread() {
+        let agent = agent();
+        let (session, _events) = agent.sessions.open();
+        let toast = ToastPacket::new("title".to_string(), "body".to_string());
+        let response = handle_packet(&agent, &session, toast, Some(GameThread::Server));
+        assert_eq!(error_kind(response), ErrorKind::UnsupportedPacket);
+    }
+
+    #[test]
+    fn plugin_hooks_are_not_entry_points() {
+        let agent = agent();
+        let (session, _events) = agent.sessions.open();
+        for entry in [REGISTER_SYMBOL, ON_UNLOAD_SYMBOL] {
+            let call = Packet::Call(CallPacket {
+                plugin: "example".to_string(),
+                entry: entry.to_string(),
+                payload: Vec::new(),
+            });
+            let error = error(handle_packet(&agent, &session, call, None));
+            assert_eq!(error.kind, ErrorKind::Plugin);
+            assert!(error.message.contains("reserved"), "{}", error.message);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Natives of `YarnMappingResolver`, which looks names up in mappings read on the Rust side.
+//!
+//! Names are mapped from the namespace plugins are written against, Yarn's or Mojang's, to the
+//! one the game runs with.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use jni::JNIEnv;
+use jni::objects::{JClass, JObject, JString};
+use jni::sys::jstring;
+use mc_connect::mappings::{
+    self, Mappings, MappingsError,
+    cache::{self, MappingsKind},
+};
+
+use crate::exception::throw_panics;
+
+const AGENT_CLASS: &str = "com/codemob/mcconnect/RustAgent";
+
+static MAPPINGS: OnceLock<Resolver> = OnceLock::new();
+
+/// Classes of every game version, by their name in a namespace that doesn't change across
+/// versions, to find which namespace the game runs with.
+const PROBES: &[(&str, &[&str])] = &[
+    (
+        mappings::INTERMEDIARY,
+        &[
+            "net/minecraft/class_310",
+            "net/minecraft/class_1937",
+            "net/minecraft/class_1297",
+        ],
+    ),
+    (
+        mappings::MOJMAP,
+        &[
+            "net/minecraft/client/Minecraft",
+            "net/minecraft/world/level/Level",
+            "net/minecraft/world/entity/Entity",
+        ],
+    ),
+];
+
+/// Namespaces the game may run with, in the order they are probed: `named` in a development
+/// environment, `intermediary` with Fabric, `mojmap` with NeoForge and `official` in vanilla.
+const RUNTIME_NAMESPACES: &[&str] = &[
+    mappings::NAMED,
+    mappings::INTERMEDIARY,
+    mappings::MOJMAP,
+    mappings::OFFICIAL,
+];
+
+struct Resolver {
+    mappings: Mappings,
+    /// The namespace plugins use.
+    source: usize,
+    /// Yarn's `named` namespace, which the agent's own lookups use.
+    yarn: usize,
+    /// The namespace the game runs with.
+    target: usize,
+}
+
+impl Resolver {
+    /// Reads Yarn's mappings, joined with Mojang's on the obfuscated names if given, and maps
+    /// names to `target`, or to the namespace the game turns out to run with.
+    fn read(
+        env: &mut JNIEnv,
+        yarn: String,
+        mojmap: Option<String>,
+        source: &str,
+        target: Option<&str>,
+    ) -> Result<Self, String> {
+        let mappings = match mojmap {
+            Some(mojmap) => Mappings::read(yarn)
+                .and_then(|yarn| yarn.compose(&Mappings::read(mojmap)?, mappings::OFFICIAL)),
+            None => Mappings::read(yarn),
+        }
+        .map_err(|e| e.to_string())?;
+        let namespace = |name: &str| {
+            mappings
+                .namespace(name)
+                .ok_or_else(|| MappingsError::MissingNamespace(name.to_string()).to_string())
+        };
+        let source = namespace(source)?;
+        let yarn = namespace(mappings::NAMED)?;
+        let target = match target {
+            Some(target) => namespace(target)?,
+            None => detect_namespace(env, &mappings)?,
+        };
+        Ok(Self {
+            mappings,
+            source,
+            yarn,
+            target,
+        })
+    }
+}
+
+/// The namespace names are mapped to, which the game runs with, once the mappings are loaded.
+pub fn runtime_namespace() -> Option<&'static str> {
+    let resolver = MAPPINGS.get()?;
+    Some(&resolver.mappings.namespaces()[resolver.target])
+}
+
+/// Finds the namespace whose names of a few known classes the game can load.
+fn detect_namespace(env: &mut JNIEnv, mappings: &Mappings) -> Result<usize, String> {
+    let Some((probe_ns, probes)) = PROBES
+        .iter()
+        .find_map(|(namespace, probes)| Some((mappings.namespace(namespace)?, probes)))
+    else {
+        return Err("mappings have no namespace to find the game's classes by".to_string());
+    };
+    let candidates = RUNTIME_NAMESPACES
+        .iter()
+        .filter_map(|namespace| mappings.namespace(namespace))
+        .collect::<Vec<_>>();
+    for &ns in &candidates {
+        let mut loaded = true;
+        for probe in probes.iter() {
+            let class_name = mappings.map_class_name(probe, probe_ns, ns);
+            if !can_load(env, class_name).map_err(|e| e.to_string())? {
+                loaded = false;
+                break;
+            }
+        }
+        if loaded {
+            return Ok(ns);
+        }
+    }
+    let tried = candidates
+        .iter()
+        .map(|&ns| mappings.namespaces()[ns].as_str())
+        .collect::<Vec<_>>();
+    Err(format!(
+        "the game's classes aren't named as in any of {:?}; pass the target namespace",
+        tried
+    ))
+}
+
+/// Whether the game's class loader finds a class, without initializing it.
+fn can_load(env: &mut JNIEnv, class_name: &str) -> jni::errors::Result<bool> {
+    let class_loader = env
+        .get_static_field(AGENT_CLASS, "classLoader", "Ljava/lang/ClassLoader;")?
+        .l()?;
+    let name = env.new_string(class_name.replace('/', "."))?;
+    let result = env.call_static_method(
+        "java/lang/Class",
+        "forName",
+        "(Ljava/lang/String;ZLjava/lang/ClassLoader;)Ljava/lang/Class;",
+        &[(&name).into(), false.into(), (&class_loader).into()],
+    );
+    match result {
+        Ok(_) => Ok(true),
+        Err(jni::errors::Error::JavaException) => {
+            // Usually a ClassNotFoundException
+            env.exception_clear()?;
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Finds mappings of a game version without downloading them: in `local` if the agent was given
+/// any, in the cache otherwise. Answers null if they aren't cached.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_findMappings(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    version: JString<'_>,
+    kind: JString<'_>,
+    local: JString<'_>,
+) -> jstring {
+    throw_panics(&mut env, std::ptr::null_mut(), |env| {
+        let Some(version) = get_string(env, &version) else {
+            return std::ptr::null_mut();
+        };
+        let Some(kind) = get_kind(env, &kind) else {
+            return std::ptr::null_mut();
+        };
+        let Some(local) = get_nullable_string(env, &local) else {
+            return std::ptr::null_mut();
+        };
+        let path = match local {
+            Some(local) => match cache::find_local(Path::new(&local), kind, &version) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    let _ = env.throw_new("java/io/FileNotFoundException", e.to_string());
+                    return std::ptr::null_mut();
+                }
+            },
+            None => game_dir(env).and_then(|game_dir| cache::find(&game_dir, kind, &version)),
+        };
+        match path {
+            Some(path) => new_string(env, path.display().to_string()),
+            None => std::ptr::null_mut(),
+        }
+    })
+}
+
+/// Where to download mappings of a game version to, creating the cache directory.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_cachePath(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    version: JString<'_>,
+    kind: JString<'_>,
+) -> jstring {
+    throw_panics(&mut env, std::ptr::null_mut(), |env| {
+        let Some(version) = get_string(env, &version) else {
+            return std::ptr::null_mut();
+        };
+        let Some(kind) = get_kind(env, &kind) else {
+            return std::ptr::null_mut();
+        };
+        let Some(game_dir) = game_dir(env) else {
+            return std::ptr::null_mut();
+        };
+        let path = cache::path(&game_dir, kind, &version);
+        let dir = &cache::dirs(&game_dir)[0];
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            let _ = env.throw_new(
+                "java/io/IOException",
+                format!("failed to create {}: {}", dir.display(), e),
+            );
+            return std::ptr::null_mut();
+        }
+        new_string(env, path.display().to_string())
+    })
+}
+
+/// Reads the mappings to resolve names with, from `source` to `target`, which is worked out if
+/// it is null.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_load(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    yarn: JString<'_>,
+    mojmap: JString<'_>,
+    source: JString<'_>,
+    target: JString<'_>,
+) {
+    throw_panics(&mut env, (), |env| {
+        let Some(yarn) = get_string(env, &yarn) else {
+            return;
+        };
+        let Some(mojmap) = get_nullable_string(env, &mojmap) else {
+            return;
+        };
+        let Some(source) = get_string(env, &source) else {
+            return;
+        };
+        let Some(target) = get_nullable_string(env, &target) else {
+            return;
+        };
+        match Resolver::read(env, yarn, mojmap, &source, target.as_deref()) {
+            Ok(resolver) => {
+                if MAPPINGS.set(resolver).is_err() {
+                    let _ = env.throw_new(
+                        "java/lang/IllegalStateException",
+                        "mappings are already loaded",
+                    );
+                }
+            }
+            Err(e) => {
+                let _ = env.throw_new("java/io/IOException", e);
+            }
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_getClassMapping(
+    mut env: JNIEnv<'_>,
+    this: JObject<'_>,
+    class_name: JString<'_>,
+) -> jstring {
+    resolve(&mut env, &this, |env, resolver, source| {
+        let class_name = get_string(env, &class_name)?;
+        Some(
+            resolver
+                .mappings
+                .map_class_name(&class_name, source, resolver.target)
+                .to_string(),
+        )
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_findMethodMapping(
+    mut env: JNIEnv<'_>,
+    this: JObject<'_>,
+    class_name: JString<'_>,
+    method_name: JString<'_>,
+    desc: JString<'_>,
+) -> jstring {
+    resolve(&mut env, &this, |env, resolver, source| {
+        let class_name = get_string(env, &class_name)?;
+        let method_name = get_string(env, &method_name)?;
+        let desc = get_nullable_string(env, &desc)?;
+        let method =
+            resolver
+                .mappings
+                .method(&class_name, &method_name, desc.as_deref(), source)?;
+        Some(method.name(resolver.target).to_string())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_findFieldMapping(
+    mut env: JNIEnv<'_>,
+    this: JObject<'_>,
+    class_name: JString<'_>,
+    field_name: JString<'_>,
+    desc: JString<'_>,
+) -> jstring {
+    resolve(&mut env, &this, |env, resolver, source| {
+        let class_name = get_string(env, &class_name)?;
+        let field_name = get_string(env, &field_name)?;
+        let desc = get_nullable_string(env, &desc)?;
+        let field = resolver
+            .mappings
+            .field(&class_name, &field_name, desc.as_deref(), source)?;
+        Some(field.name(resolver.target).to_string())
+    })
+}
+
+/// Takes and returns a binary name, with dots.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_unmapClassName(
+    mut env: JNIEnv<'_>,
+    this: JObject<'_>,
+    class_name: JString<'_>,
+) -> jstring {
+    resolve(&mut env, &this, |env, resolver, source| {
+        let class_name = get_string(env, &class_name)?.replace('.', "/");
+        let named = resolver
+            .mappings
+            .map_class_name(&class_name, resolver.target, source);
+        Some(named.replace('/', "."))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_unmapMethodName(
+    mut env: JNIEnv<'_>,
+    this: JObject<'_>,
+    class_name: JString<'_>,
+    method_name: JString<'_>,
+) -> jstring {
+    resolve(&mut env, &this, |env, resolver, source| {
+        let class_name = get_string(env, &class_name)?.replace('.', "/");
+        let method_name = get_string(env, &method_name)?;
+        Some(
+            resolver
+                .mappings
+                .method(&class_name, &method_name, None, resolver.target)
+                .map_or(method_name.clone(), |method| {
+                    method.name(source).to_string()
+                }),
+        )
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codemob_mcconnect_YarnMappingResolver_unmapFieldName(
+    mut env: JNIEnv<'_>,
+    this: JObject<'_>,
+    class_name: JString<'_>,
+    field_name: JString<'_>,
+) -> jstring {
+    resolve(&mut env, &this, |env, resolver, source| {
+        let class_name = get_string(env, &class_name)?.replace('.', "/");
+        let field_name = get_string(env, &field_name)?;
+        Some(
+            resolver
+                .mappings
+                .field(&class_name, &field_name, None, resolver.target)
+                .map_or(field_name.clone(), |field| field.name(source).to_string()),
+        )
+    })
+}
+
+/// Runs a lookup against the loaded mappings from the namespace `this` takes names in,
+/// answering null if it finds nothing or throws.
+fn resolve<F>(env: &mut JNIEnv, this: &JObject, lookup: F) -> jstring
+where
+    F: FnOnce(&mut JNIEnv, &Resolver, usize) -> Option<String>,
+{
+    let Some(resolver) = MAPPINGS.get() else {
+        let _ = env.throw_new("java/lang/IllegalStateException", "mappings aren't loaded");
+        return std::ptr::null_mut();
+    };
+    throw_panics(env, std::ptr::null_mut(), |env| {
+        let Ok(yarn) = env.get_field(this, "yarn", "Z").and_then(|yarn| yarn.z()) else {
+            return std::ptr::null_mut();
+        };
+        let source = if yarn { resolver.yarn } else { resolver.source };
+        match lookup(env, resolver, source) {
+            Some(name) => new_string(env, name),
+            None => std::ptr::null_mut(),
+        }
+    })
+}
+
+/// The game runs in its directory.
+fn game_dir(env: &mut JNIEnv) -> Option<std::path::PathBuf> {
+    match std::env::current_dir() {
+        Ok(game_dir) => Some(game_dir),
+        Err(e) => {
+            let _ = env.throw_new(
+                "java/io/IOException",
+                format!("failed to find the game directory: {}", e),
+            );
+            None
+        }
+    }
+}
+
+fn new_string(env: &mut JNIEnv, string: String) -> jstring {
+    match env.new_string(string) {
+        Ok(string) => string.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Reads a string argument, leaving a `NullPointerException` pending if it is null.
+fn get_string(env: &mut JNIEnv, string: &JString) -> Option<String> {
+    if string.is_null() {
+        let _ = env.throw_new("java/lang/NullPointerException", "name is null");
+        return None;
+    }
+    env.get_string(string).ok().map(Into::into)
+}
+
+fn get_kind(env: &mut JNIEnv, kind: &JString) -> Option<MappingsKind> {
+    match get_string(env, kind)?.parse() {
+        Ok(kind) => Some(kind),
+        Err(e) => {
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            None
+        }
+    }
+}
+
+/// Reads a string argument that may be null, giving `Some(None)` for null.
+fn get_nullable_string(env: &mut JNIEnv, string: &JString) -> Option<Option<String>> {
+    if string.is_null() {
+        return Some(None);
+    }
+    get_string(env, string).map(Some)
+}
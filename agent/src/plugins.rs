@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use jni::JavaVM;
+use libloading::Library;
+use mc_connect::communication::PluginInfo;
+use mc_connect::plugin::{self, EntryFn, ON_UNLOAD_SYMBOL, OnUnloadFn, RESERVED_PREFIX};
+
+use crate::channels::ChannelRegistry;
+
+/// Directory next to a plugin's library where the copy that is actually loaded is made.
+const LOADED_DIR: &str = ".loaded";
+
+/// Plugins loaded into the game, which stay loaded until they are unloaded.
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Mutex<BTreeMap<String, Plugin>>,
+    /// Numbers the copies of libraries, so every load has a path of its own.
+    loads: AtomicU64,
+}
+
+struct Plugin {
+    info: PluginInfo,
+    /// Shared with the plugin's channel handlers and with calls in progress, which keep it
+    /// loaded after it is unloaded until they return.
+    library: Arc<Library>,
+    /// The copy of the library that was loaded, kept so backtraces of its panics can be read.
+    copy: PathBuf,
+}
+
+impl PluginRegistry {
+    pub fn load(&self, channels: &ChannelRegistry, lib: &Path) -> Result<PluginInfo, String> {
+        let name = name(lib)?;
+        let (info, _) = self.swap(channels, name, |loaded| match loaded {
+            Some(plugin) => Err(format!("plugin {} is already loaded", plugin.info.name)),
+            None => Ok(lib.to_path_buf()),
+        })?;
+        Ok(info)
+    }
+
+    /// Loads the library again from where it was loaded. The plugin that was loaded keeps
+    /// running if the new copy fails to load.
+    pub fn reload(
+        &self,
+        vm: &JavaVM,
+        channels: &ChannelRegistry,
+        name: &str,
+    ) -> Result<PluginInfo, String> {
+        let (info, old) = self.swap(channels, name.to_string(), |loaded| match loaded {
+            Some(plugin) => Ok(plugin.info.lib.clone()),
+            None => Err(format!("plugin {} is not loaded", name)),
+        })?;
+        if let Some(old) = old {
+            close(vm, old);
+        }
+        Ok(info)
+    }
+
+    pub fn unload(
+        &self,
+        vm: &JavaVM,
+        channels: &ChannelRegistry,
+        name: &str,
+    ) -> Result<(), String> {
+        let plugin = self.take(name)?;
+        channels.unregister(name);
+        close(vm, plugin);
+        Ok(())
+    }
+
+    /// Loads `lib`, or reloads it if a plugin of the same name is loaded.
+    pub fn load_or_reload(
+        &self,
+        vm: &JavaVM,
+        channels: &ChannelRegistry,
+        lib: &Path,
+    ) -> Result<PluginInfo, String> {
+        let name = name(lib)?;
+        let (info, old) = self.swap(channels, name, |_| Ok(lib.to_path_buf()))?;
+        if let Some(old) = old {
+            close(vm, old);
+        }
+        Ok(info)
+    }
+
+    pub fn call(
+        &self,
+        vm: &JavaVM,
+        name: &str,
+        entry: &str,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        // They have other signatures than entry points
+        if entry.starts_with(RESERVED_PREFIX) {
+            return Err(format!("{} is reserved and can't be called", entry));
+        }
+        let library = match self.plugins.lock().unwrap().get(name) {
+            Some(plugin) => Arc::clone(&plugin.library),
+            None => return Err(format!("plugin {} is not loaded", name)),
+        };
+        // SAFETY: entry points are called with the signature plugins are documented to export
+        let entry_fn = unsafe { library.get::<EntryFn>(entry.as_bytes()) }
+            .map_err(|e| format!("plugin {} has no entry point {}: {}", name, entry, e))?;
+        entry_fn(vm, payload.into()).into_result()
+    }
+
+    pub fn list(&self) -> Vec<PluginInfo> {
+        self.plugins
+            .lock()
+            .unwrap()
+            .values()
+            .map(|plugin| plugin.info.clone())
+            .collect()
+    }
+
+    fn take(&self, name: &str) -> Result<Plugin, String> {
+        self.plugins
+            .lock()
+            .unwrap()
+            .remove(name)
+            .ok_or_else(|| format!("plugin {} is not loaded", name))
+    }
+
+    /// Opens the library `lib` picks given the plugin loaded as `name`, and puts it in that
+    /// plugin's place, returning the plugin it replaced to be closed.
+    fn swap(
+        &self,
+        channels: &ChannelRegistry,
+        name: String,
+        lib: impl FnOnce(Option<&Plugin>) -> Result<PathBuf, String>,
+    ) -> Result<(PluginInfo, Option<Plugin>), String> {
+        let mut plugins = self.plugins.lock().unwrap();
+        let lib = lib(plugins.get(&name))?;
+        // Registering the new copy's channels replaces those of the plugin it replaces
+        let plugin = self.open(channels, name.clone(), &lib)?;
+        let info = plugin.info.clone();
+        Ok((info, plugins.insert(name, plugin)))
+    }
+
+    fn open(&self, channels: &ChannelRegistry, name: String, lib: &Path) -> Result<Plugin, String> {
+        // The system may keep a library in memory after it is unloaded and hand it out again
+        // when a library of the same path is loaded, so every load gets a fresh copy
+        let copy = self.copy_path(&name, lib);
+        if let Some(dir) = copy.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::copy(lib, &copy).map_err(|e| format!("failed to copy {}: {}", lib.display(), e))?;
+        // SAFETY: loading a plugin runs its initializers, which plugins are trusted with
+        let library = match unsafe { Library::new(&copy) } {
+            Ok(library) => Arc::new(library),
+            Err(e) => {
+                let _ = fs::remove_file(&copy);
+                return Err(e.to_string());
+            }
+        };
+
+        let channels = match channels.register_library(&name, &library) {
+            Ok(channels) => channels,
+            Err(e) => {
+                let _ = fs::remove_file(&copy);
+                return Err(e);
+            }
+        };
+        Ok(Plugin {
+            info: PluginInfo {
+                name,
+                lib: lib.to_path_buf(),
+                channels,
+            },
+            library,
+            copy,
+        })
+    }
+
+    fn copy_path(&self, name: &str, lib: &Path) -> PathBuf {
+        let load = self.loads.fetch_add(1, Ordering::Relaxed);
+        let mut file_name = format!("{}-{}", name, load);
+        if let Some(extension) = lib.extension() {
+            file_name.push('.');
+            file_name.push_str(&extension.to_string_lossy());
+        }
+        lib.parent()
+            .unwrap_or(Path::new("."))
+            .join(LOADED_DIR)
+            .join(file_name)
+    }
+}
+
+fn name(lib: &Path) -> Result<String, String> {
+    plugin::plugin_name(lib).ok_or_else(|| format!("{} is not a library", lib.display()))
+}
+
+/// Lets a plugin clean up once packets are no longer routed to it; the library is unloaded
+/// once no call into it is left.
+fn close(vm: &JavaVM, plugin: Plugin) {
+    // SAFETY: the hook is called with the signature plugins are documented to export
+    let on_unload = unsafe {
+        plugin
+            .library
+            .get::<OnUnloadFn>(ON_UNLOAD_SYMBOL.as_bytes())
+    };
+    if let Ok(on_unload) = on_unload {
+        on_unload(vm);
+    }
+    // Calls still running keep the library mapped, which doesn't need the file
+    let _ = fs::remove_file(&plugin.copy);
+}
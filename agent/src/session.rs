@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use mc_connect::communication::{
+    ErrorKind, ErrorPacket, Event, EventKind, Packet, SessionInfoPacket,
+};
+use tokio::sync::mpsc;
+
+use crate::handles::HandleTable;
+
+/// A connected client and the state that belongs to it.
+pub struct Session {
+    pub id: u64,
+    subscriptions: Mutex<HashSet<EventKind>>,
+    events: mpsc::UnboundedSender<Packet>,
+    pub handles: HandleTable,
+}
+
+/// Every open session, plus the exclusive lock one of them may hold.
+#[derive(Default)]
+pub struct SessionRegistry {
+    num_sessions: AtomicU64,
+    sessions: Mutex<HashMap<u64, Arc<Session>>>,
+    locked_by: Mutex<Option<u64>>,
+}
+
+impl SessionRegistry {
+    /// Registers a new session. Events for it are delivered through the returned receiver.
+    pub fn open(&self) -> (Arc<Session>, mpsc::UnboundedReceiver<Packet>) {
+        let (events, receiver) = mpsc::unbounded_channel();
+        let session = Arc::new(Session {
+            id: self.num_sessions.fetch_add(1, Ordering::AcqRel) + 1,
+            subscriptions: Mutex::new(HashSet::new()),
+            events,
+            handles: HandleTable::default(),
+        });
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.id, Arc::clone(&session));
+        self.broadcast(Event::SessionOpened {
+            session: session.id,
+        });
+        (session, receiver)
+    }
+
+    pub fn close(&self, session: &Session) {
+        self.sessions.lock().unwrap().remove(&session.id);
+        let was_locked = {
+            let mut locked_by = self.locked_by.lock().unwrap();
+            let was_locked = *locked_by == Some(session.id);
+            if was_locked {
+                *locked_by = None;
+            }
+            was_locked
+        };
+        if was_locked {
+            self.broadcast(Event::Unlocked {
+                session: session.id,
+            });
+        }
+        self.broadcast(Event::SessionClosed {
+            session: session.id,
+        });
+    }
+
+    /// Handles the packets that manage sessions, returning any other packet unchanged.
+    pub fn handle(&self, session: &Session, packet: Packet) -> Result<Packet, Packet> {
+        match packet {
+            Packet::Lock => Ok(self.lock(session)),
+            Packet::Unlock => Ok(self.unlock(session)),
+            Packet::Subscribe(kind) => {
+                session.subscriptions.lock().unwrap().insert(kind);
+                Ok(Packet::Confirmation)
+            }
+            Packet::Unsubscribe(kind) => {
+                session.subscriptions.lock().unwrap().remove(&kind);
+                Ok(Packet::Confirmation)
+            }
+            Packet::Sessions => {
+                let mut sessions = self
+                    .sessions
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .copied()
+                    .collect::<Vec<_>>();
+                sessions.sort_unstable();
+                Ok(Packet::SessionInfo(SessionInfoPacket {
+                    session: session.id,
+                    sessions,
+                    locked_by: *self.locked_by.lock().unwrap(),
+                }))
+            }
+            packet => match *self.locked_by.lock().unwrap() {
+                Some(owner) if owner != session.id => Ok(ErrorPacket::new(
+                    ErrorKind::Locked,
+                    format!("agent is locked by session {}", owner),
+                )),
+                _ => Err(packet),
+            },
+        }
+    }
+
+    fn lock(&self, session: &Session) -> Packet {
+        {
+            let mut locked_by = self.locked_by.lock().unwrap();
+            match *locked_by {
+                Some(owner) if owner == session.id => return Packet::Confirmation,
+                Some(owner) => {
+                    return ErrorPacket::new(
+                        ErrorKind::Locked,
+                        format!("agent is locked by session {}", owner),
+                    );
+                }
+                None => *locked_by = Some(session.id),
+            }
+        }
+        self.broadcast(Event::Locked {
+            session: session.id,
+        });
+        Packet::Confirmation
+    }
+
+    fn unlock(&self, session: &Session) -> Packet {
+        {
+            let mut locked_by = self.locked_by.lock().unwrap();
+            match *locked_by {
+                Some(owner) if owner == session.id => *locked_by = None,
+                Some(owner) => {
+                    return ErrorPacket::new(
+                        ErrorKind::Locked,
+                        format!("agent is locked by session {}", owner),
+                    );
+                }
+                None => return Packet::Confirmation,
+            }
+        }
+        self.broadcast(Event::Unlocked {
+            session: session.id,
+        });
+        Packet::Confirmation
+    }
+
+    fn broadcast(&self, event: Event) {
+        for session in self.sessions.lock().unwrap().values() {
+            if session
+                .subscriptions
+                .lock()
+                .unwrap()
+                .contains(&event.kind())
+            {
+                // A closed session's receiver is gone; it is removed from the registry shortly
+                let _ = session.events.send(Packet::Event(event.clone()));
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use jni::JNIEnv;
+use jni::objects::{JClass, JObject, JString, JValue, JValueOwned};
+use mc_connect::communication::value::{
+    InvalidDescriptor, JavaType, JavaValue, STRING_CLASS, UnexpectedValue,
+};
+use mc_connect::communication::{ErrorKind, ErrorPacket, Packet};
+
+use crate::exception::{TOOLS_CLASS, java_error};
+use crate::handles::HandleTable;
+
+pub enum ValueError {
+    Jni(jni::errors::Error),
+    InvalidArgument(String),
+}
+
+impl ValueError {
+    pub fn into_packet(self, env: &mut JNIEnv) -> Packet {
+        match self {
+            ValueError::Jni(e) => java_error(env, e),
+            ValueError::InvalidArgument(message) => {
+                ErrorPacket::new(ErrorKind::InvalidArgument, message)
+            }
+        }
+    }
+}
+
+impl From<jni::errors::Error> for ValueError {
+    fn from(error: jni::errors::Error) -> Self {
+        ValueError::Jni(error)
+    }
+}
+
+impl From<UnexpectedValue> for ValueError {
+    fn from(error: UnexpectedValue) -> Self {
+        ValueError::InvalidArgument(error.to_string())
+    }
+}
+
+impl From<InvalidDescriptor> for ValueError {
+    fn from(error: InvalidDescriptor) -> Self {
+        ValueError::InvalidArgument(error.to_string())
+    }
+}
+
+/// Converts `value` for a JNI call taking a `ty`.
+pub fn to_jvalue<'local>(
+    env: &mut JNIEnv<'local>,
+    handles: &HandleTable,
+    value: JavaValue,
+    ty: &JavaType,
+) -> Result<JValueOwned<'local>, ValueError> {
+    Ok(match value.convert(ty)? {
+        JavaValue::Boolean(value) if *ty == JavaType::Boolean => JValueOwned::Bool(value.into()),
+        JavaValue::Byte(value) if *ty == JavaType::Byte => JValueOwned::Byte(value),
+        JavaValue::Char(value) if *ty == JavaType::Char => JValueOwned::Char(value),
+        JavaValue::Short(value) if *ty == JavaType::Short => JValueOwned::Short(value),
+        JavaValue::Int(value) if *ty == JavaType::Int => JValueOwned::Int(value),
+        JavaValue::Long(value) if *ty == JavaType::Long => JValueOwned::Long(value),
+        JavaValue::Float(value) if *ty == JavaType::Float => JValueOwned::Float(value),
+        JavaValue::Double(value) if *ty == JavaType::Double => JValueOwned::Double(value),
+        value => JValueOwned::Object(to_object(env, handles, value, ty)?),
+    })
+}
+
+/// Converts `value` to an object assignable to `ty`, boxing primitives.
+pub fn to_object<'local>(
+    env: &mut JNIEnv<'local>,
+    handles: &HandleTable,
+    value: JavaValue,
+    ty: &JavaType,
+) -> Result<JObject<'local>, ValueError> {
+    let value = value.convert(ty)?;
+    if let Some(primitive) = value.primitive_type() {
+        let box_class = primitive.box_class().unwrap();
+        let desc = format!("({})L{};", primitive.descriptor(), box_class);
+        let value = to_jvalue(env, handles, value, &primitive)?;
+        return Ok(env
+            .call_static_method(box_class, "valueOf", desc, &[value.borrow()])?
+            .l()?);
+    }
+
+    Ok(match value {
+        JavaValue::Null => JObject::null(),
+        JavaValue::String(string) => env.new_string(string)?.into(),
+        JavaValue::Text(string) => {
+            let string = env.new_string(string)?;
+            env.call_static_method(
+                TOOLS_CLASS,
+                "literalText",
+                "(Ljava/lang/String;)Ljava/lang/Object;",
+                &[(&string).into()],
+            )?
+            .l()?
+        }
+        JavaValue::Enum { class_name, name } => {
+            let class_name = env.new_string(class_name)?;
+            let name = env.new_string(name)?;
+            env.call_static_method(
+                TOOLS_CLASS,
+                "getEnumConstant",
+                "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/Object;",
+                &[(&class_name).into(), (&name).into()],
+            )?
+            .l()?
+        }
+        JavaValue::Object { handle, class_name } => {
+            let object = handles.get(handle).ok_or_else(|| {
+                ValueError::InvalidArgument(format!("unknown handle to a {}", class_name))
+            })?;
+            env.new_local_ref(object)?
+        }
+        JavaValue::Array(values) => {
+            let element = match ty {
+                JavaType::Array(element) => element.as_ref().clone(),
+                // An array passed as an `Object` is an `Object[]`
+                ty => ty.clone(),
+            };
+            to_array(env, handles, values, &element)?
+        }
+        value => unreachable!("{:?} is boxed above", value),
+    })
+}
+
+fn to_array<'local>(
+    env: &mut JNIEnv<'local>,
+    handles: &HandleTable,
+    values: Vec<JavaValue>,
+    element: &JavaType,
+) -> Result<JObject<'local>, ValueError> {
+    let length = values.len() as i32;
+    macro_rules! primitive_array {
+        ($new:ident, $set:ident, $variant:ident) => {{
+            let elements = values
+                .into_iter()
+                .map(|value| match value {
+                    JavaValue::$variant(value) => value,
+                    // `convert` already checked every element
+                    value => unreachable!("{:?} in a {} array", value, element),
+                })
+                .collect::<Vec<_>>();
+            let array = env.$new(length)?;
+            env.$set(&array, 0, &elements)?;
+            JObject::from(array)
+        }};
+    }
+
+    Ok(match element {
+        JavaType::Boolean => {
+            let elements = values
+                .into_iter()
+                .map(|value| matches!(value, JavaValue::Boolean(true)).into())
+                .collect::<Vec<u8>>();
+            let array = env.new_boolean_array(length)?;
+            env.set_boolean_array_region(&array, 0, &elements)?;
+            JObject::from(array)
+        }
+        JavaType::Byte => primitive_array!(new_byte_array, set_byte_array_region, Byte),
+        JavaType::Char => primitive_array!(new_char_array, set_char_array_region, Char),
+        JavaType::Short => primitive_array!(new_short_array, set_short_array_region, Short),
+        JavaType::Int => primitive_array!(new_int_array, set_int_array_region, Int),
+        JavaType::Long => primitive_array!(new_long_array, set_long_array_region, Long),
+        JavaType::Float => primitive_array!(new_float_array, set_float_array_region, Float),
+        JavaType::Double => primitive_array!(new_double_array, set_double_array_region, Double),
+        JavaType::Void => return Err(ValueError::InvalidArgument("void array".to_string())),
+        JavaType::Object(_) | JavaType::Array(_) => {
+            let desc = env.new_string(element.descriptor())?;
+            let class = JClass::from(
+                env.call_static_method(
+                    TOOLS_CLASS,
+                    "getType",
+                    "(Ljava/lang/String;)Ljava/lang/Class;",
+                    &[(&desc).into()],
+                )?
+                .l()?,
+            );
+            let array = env.new_object_array(length, &class, JObject::null())?;
+            for (i, value) in values.into_iter().enumerate() {
+                let value = to_object(env, handles, value, element)?;
+                env.set_object_array_element(&array, i as i32, value)?;
+            }
+            JObject::from(array)
+        }
+    })
+}
+
+/// Converts a primitive, or an object by its runtime class; objects without a value
+/// representation are added to `handles`.
+pub fn from_jvalue(
+    env: &mut JNIEnv,
+    handles: &HandleTable,
+    value: JValueOwned,
+) -> jni::errors::Result<JavaValue> {
+    Ok(match value {
+        JValueOwned::Object(object) => return from_object(env, handles, &object),
+        JValueOwned::Bool(value) => JavaValue::Boolean(value != 0),
+        JValueOwned::Byte(value) => JavaValue::Byte(value),
+        JValueOwned::Char(value) => JavaValue::Char(value),
+        JValueOwned::Short(value) => JavaValue::Short(value),
+        JValueOwned::Int(value) => JavaValue::Int(value),
+        JValueOwned::Long(value) => JavaValue::Long(value),
+        JValueOwned::Float(value) => JavaValue::Float(value),
+        JValueOwned::Double(value) => JavaValue::Double(value),
+        JValueOwned::Void => JavaValue::Null,
+    })
+}
+
+pub fn from_object(
+    env: &mut JNIEnv,
+    handles: &HandleTable,
+    object: &JObject,
+) -> jni::errors::Result<JavaValue> {
+    if object.is_null() {
+        return Ok(JavaValue::Null);
+    }
+    if env.is_instance_of(object, STRING_CLASS)? {
+        let string = env.new_local_ref(object)?;
+        return Ok(JavaValue::String(get_string(env, string)?));
+    }
+
+    const PRIMITIVES: [JavaType; 8] = [
+        JavaType::Boolean,
+        JavaType::Byte,
+        JavaType::Char,
+        JavaType::Short,
+        JavaType::Int,
+        JavaType::Long,
+        JavaType::Float,
+        JavaType::Double,
+    ];
+    for primitive in PRIMITIVES {
+        if env.is_instance_of(object, primitive.box_class().unwrap())? {
+            // `Integer.intValue()`, `Boolean.booleanValue()` and so on
+            let unbox = format!("{}Value", primitive);
+            let value =
+                env.call_method(object, unbox, format!("(){}", primitive.descriptor()), &[])?;
+            return from_jvalue(env, handles, value);
+        }
+    }
+
+    let is_text = env
+        .call_static_method(
+            TOOLS_CLASS,
+            "isText",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(object)],
+        )?
+        .z()?;
+    if is_text {
+        return Ok(JavaValue::Text(call_string_tool(
+            env,
+            "textToString",
+            "(Ljava/lang/Object;)Ljava/lang/String;",
+            object,
+        )?));
+    }
+
+    if env.is_instance_of(object, "java/lang/Enum")? {
+        return Ok(JavaValue::Enum {
+            class_name: call_string_tool(
+                env,
+                "getEnumClassName",
+                "(Ljava/lang/Enum;)Ljava/lang/String;",
+                object,
+            )?,
+            name: call_string_tool(
+                env,
+                "getEnumName",
+                "(Ljava/lang/Enum;)Ljava/lang/String;",
+                object,
+            )?,
+        });
+    }
+
+    let class = env.get_object_class(object)?;
+    if env.call_method(&class, "isArray", "()Z", &[])?.z()? {
+        let length = env
+            .call_static_method(
+                "java/lang/reflect/Array",
+                "getLength",
+                "(Ljava/lang/Object;)I",
+                &[JValue::Object(object)],
+            )?
+            .i()?;
+        let values = (0..length)
+            .map(|i| {
+                env.with_local_frame(4, |env| {
+                    let element = env
+                        .call_static_method(
+                            "java/lang/reflect/Array",
+                            "get",
+                            "(Ljava/lang/Object;I)Ljava/lang/Object;",
+                            &[JValue::Object(object), JValue::Int(i)],
+                        )?
+                        .l()?;
+                    from_object(env, handles, &element)
+                })
+            })
+            .collect::<jni::errors::Result<_>>()?;
+        return Ok(JavaValue::Array(values));
+    }
+
+    let class_name = call_string_tool(
+        env,
+        "getClassName",
+        "(Ljava/lang/Object;)Ljava/lang/String;",
+        object,
+    )?;
+    let handle = handles.insert(env.new_global_ref(object)?);
+    Ok(JavaValue::Object { handle, class_name })
+}
+
+fn get_string(env: &mut JNIEnv, string: JObject) -> jni::errors::Result<String> {
+    Ok(env.get_string(&JString::from(string))?.into())
+}
+
+/// Calls a static `Tools` method taking an object and returning a string.
+fn call_string_tool(
+    env: &mut JNIEnv,
+    method: &str,
+    desc: &str,
+    object: &JObject,
+) -> jni::errors::Result<String> {
+    let string = env
+        .call_static_method(TOOLS_CLASS, method, desc, &[JValue::Object(object)])?
+        .l()?;
+    get_string(env, string)
+}
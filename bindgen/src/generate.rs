@@ -0,0 +1,451 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use mc_connect::mappings::{ClassMapping, Mappings};
+
+use crate::Error;
+
+const BINDINGS: &str = "::mc_connect::bindings";
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// Keywords that can't be raw identifiers.
+const RESERVED: &[&str] = &["crate", "self", "Self", "super"];
+
+struct Wrapper<'a> {
+    class_name: &'a str,
+    rust_name: String,
+    /// Name of the static holding the class.
+    class_ref: String,
+    class: &'a ClassMapping,
+}
+
+pub fn generate(
+    mappings: &Mappings,
+    namespace: &str,
+    classes: &[(String, Option<String>)],
+) -> Result<String, Error> {
+    let ns = mappings
+        .namespace(namespace)
+        .ok_or_else(|| Error::MissingNamespace(namespace.to_string()))?;
+    let mut wrappers = Vec::new();
+    let mut class_refs = HashSet::new();
+    for (class_name, rust_name) in classes {
+        let class = mappings
+            .class(class_name, ns)
+            .ok_or_else(|| Error::UnknownClass(class_name.clone()))?;
+        let rust_name = rust_name.clone().unwrap_or_else(|| simple_name(class_name));
+        // Names such as `FooBar` and `Foo_Bar` differ, but not once their static is named
+        let class_ref = format!("{}_CLASS", to_snake_case(&rust_name).to_uppercase());
+        if !class_refs.insert(class_ref.clone()) {
+            return Err(Error::DuplicateName(rust_name));
+        }
+        wrappers.push(Wrapper {
+            class_name,
+            rust_name,
+            class_ref,
+            class,
+        });
+    }
+
+    let generator = Generator {
+        mappings,
+        ns,
+        wrapped: wrappers
+            .iter()
+            .map(|wrapper| (wrapper.class_name, wrapper.rust_name.as_str()))
+            .collect(),
+    };
+    let mut out = String::from("// Generated by mc-connect-bindgen, do not edit.\n");
+    for wrapper in &wrappers {
+        generator.class(&mut out, wrapper);
+    }
+    Ok(out)
+}
+
+struct Generator<'a> {
+    mappings: &'a Mappings,
+    ns: usize,
+    /// Rust names of the classes being wrapped.
+    wrapped: HashMap<&'a str, &'a str>,
+}
+
+impl Generator<'_> {
+    fn class(&self, out: &mut String, wrapper: &Wrapper) {
+        let Wrapper {
+            class_name,
+            rust_name,
+            class_ref,
+            class,
+        } = wrapper;
+
+        writeln!(
+            out,
+            "
+static {class_ref}: {BINDINGS}::ClassRef = {BINDINGS}::ClassRef::new(\"{class_name}\");
+
+/// `{class_name}`
+pub struct {rust_name}<'local>({BINDINGS}::JavaObject<'local>);
+
+impl<'local> {BINDINGS}::Binding<'local> for {rust_name}<'local> {{
+    fn class() -> &'static {BINDINGS}::ClassRef {{
+        &{class_ref}
+    }}
+
+    unsafe fn from_object(object: {BINDINGS}::JavaObject<'local>) -> Self {{
+        Self(object)
+    }}
+
+    fn as_object(&self) -> &{BINDINGS}::JavaObject<'local> {{
+        &self.0
+    }}
+
+    fn into_object(self) -> {BINDINGS}::JavaObject<'local> {{
+        self.0
+    }}
+}}
+
+#[allow(clippy::too_many_arguments, clippy::wrong_self_convention, clippy::should_implement_trait)]
+impl<'local> {rust_name}<'local> {{
+    /// A null `{rust_name}` to access static members through.
+    pub fn statics(env: &::jni::JNIEnv<'local>) -> Self {{
+        Self({BINDINGS}::JavaObject::null(env))
+    }}"
+        )
+        .unwrap();
+
+        let mut names = HashSet::from(["statics".to_string()]);
+        for method in class.methods() {
+            let name = method.name(self.ns);
+            let desc = self.mappings.map_desc(method.desc(), 0, self.ns);
+            let (params, ret) = split_method_desc(&desc);
+            let args = (0..params.len())
+                .map(|i| format!("&arg{}", i))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let params = params
+                .iter()
+                .enumerate()
+                .map(|(i, param)| format!("arg{}: {}", i, self.param_type(param)))
+                .collect::<Vec<_>>();
+
+            let (receiver, ret, call) = if name == "<init>" {
+                (
+                    "env: &::jni::JNIEnv<'local>".to_string(),
+                    "Self".to_string(),
+                    format!("construct(env, &[{}])", args),
+                )
+            } else if is_identifier(name) {
+                (
+                    "&self".to_string(),
+                    self.return_type(ret),
+                    format!("call(&self.0, &[{}])", args),
+                )
+            } else {
+                // Class initializers and synthetic methods such as lambdas
+                continue;
+            };
+            let fn_name = if name == "<init>" {
+                unique_name(&mut names, "new")
+            } else {
+                unique_name(&mut names, &to_snake_case(name))
+            };
+            let params = std::iter::once(receiver)
+                .chain(params)
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            writeln!(
+                out,
+                "
+    /// `{name}{desc}`
+    pub fn {fn_name}({params}) -> {BINDINGS}::Result<{ret}> {{
+        static METHOD: {BINDINGS}::MethodRef = {BINDINGS}::MethodRef::new(&{class_ref}, \"{name}\", \"{desc}\");
+        METHOD.{call}
+    }}"
+            )
+            .unwrap();
+        }
+
+        for field in class.fields() {
+            let name = field.name(self.ns);
+            if !is_identifier(name) {
+                continue;
+            }
+            let desc = self.mappings.map_desc(field.desc(), 0, self.ns);
+            let snake_name = to_snake_case(name);
+            let getter = unique_name(&mut names, &snake_name);
+            let setter = unique_name(&mut names, &format!("set_{}", snake_name));
+            let ret = self.return_type(&desc);
+            let param = self.param_type(&desc);
+
+            writeln!(
+                out,
+                "
+    /// `{name}:{desc}`
+    pub fn {getter}(&self) -> {BINDINGS}::Result<{ret}> {{
+        static FIELD: {BINDINGS}::FieldRef = {BINDINGS}::FieldRef::new(&{class_ref}, \"{name}\", \"{desc}\");
+        FIELD.get(&self.0)
+    }}
+
+    /// `{name}:{desc}`
+    pub fn {setter}(&self, value: {param}) -> {BINDINGS}::Result<()> {{
+        static FIELD: {BINDINGS}::FieldRef = {BINDINGS}::FieldRef::new(&{class_ref}, \"{name}\", \"{desc}\");
+        FIELD.set(&self.0, &value)
+    }}"
+            )
+            .unwrap();
+        }
+
+        out.push_str("}\n");
+    }
+
+    fn param_type(&self, desc: &str) -> String {
+        if let Some(ty) = primitive_type(desc) {
+            return ty.to_string();
+        }
+        match object_class(desc) {
+            Some("java/lang/String") => "&str".to_string(),
+            Some(class_name) if self.wrapped.contains_key(class_name) => {
+                format!("&{}<'local>", self.wrapped[class_name])
+            }
+            _ => format!("&impl {BINDINGS}::Binding<'local>"),
+        }
+    }
+
+    fn return_type(&self, desc: &str) -> String {
+        if desc == "V" {
+            return "()".to_string();
+        }
+        if let Some(ty) = primitive_type(desc) {
+            return ty.to_string();
+        }
+        match object_class(desc) {
+            Some("java/lang/String") => "Option<String>".to_string(),
+            Some(class_name) if self.wrapped.contains_key(class_name) => {
+                format!("{}<'local>", self.wrapped[class_name])
+            }
+            _ => format!("{BINDINGS}::JavaObject<'local>"),
+        }
+    }
+}
+
+fn primitive_type(desc: &str) -> Option<&'static str> {
+    Some(match desc {
+        "Z" => "bool",
+        "B" => "i8",
+        "C" => "u16",
+        "S" => "i16",
+        "I" => "i32",
+        "J" => "i64",
+        "F" => "f32",
+        "D" => "f64",
+        _ => return None,
+    })
+}
+
+/// The class of an object type descriptor, which is `None` for arrays.
+fn object_class(desc: &str) -> Option<&str> {
+    desc.strip_prefix('L')?.strip_suffix(';')
+}
+
+fn split_method_desc(desc: &str) -> (Vec<&str>, &str) {
+    let end = desc.find(')').unwrap_or(desc.len());
+    let mut params = Vec::new();
+    let mut rest = &desc[1.min(end)..end];
+    while !rest.is_empty() {
+        let mut len = rest.bytes().take_while(|&b| b == b'[').count();
+        len += match rest.as_bytes().get(len) {
+            Some(b'L') => rest[len..].find(';').map_or(rest.len() - len, |i| i + 1),
+            _ => 1,
+        };
+        let len = len.min(rest.len());
+        params.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    (params, desc.get(end + 1..).unwrap_or("V"))
+}
+
+fn simple_name(class_name: &str) -> String {
+    let simple = class_name.rsplit('/').next().unwrap_or(class_name);
+    simple.replace('$', "")
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `getUUIDString` becomes `get_uuid_string`.
+fn to_snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|c| c.is_ascii_lowercase());
+            if previous.is_ascii_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_ascii_uppercase() && next_is_lower)
+            {
+                snake.push('_');
+            }
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// Makes `name` a usable identifier that isn't in `names` yet, numbering overloads.
+fn unique_name(names: &mut HashSet<String>, name: &str) -> String {
+    let mut unique = escape(name.to_string());
+    let mut i = 1;
+    // Escaped names are checked, as `self` becomes the `self_` another member may be named
+    while names.contains(&unique) {
+        unique = escape(format!("{}_{}", name, i));
+        i += 1;
+    }
+    names.insert(unique.clone());
+    unique
+}
+
+fn escape(name: String) -> String {
+    if RESERVED.contains(&name.as_str()) || name == "_" {
+        format!("{}_", name)
+    } else if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mappings() -> Mappings {
+        Mappings::parse(
+            "tiny\t2\t0\tofficial\tnamed\n\
+             c\ta\tnet/minecraft/FooBar\n\
+             \tm\t(ILb;)La;\tc\tgetUUIDString\n\
+             \tm\t()V\td\tself\n\
+             \tm\t()V\te\tself_\n\
+             \tf\tI\tf\ttype\n\
+             c\tb\tnet/minecraft/Foo_Bar\n\
+             c\tg\tnet/minecraft/Outer$Inner\n"
+                .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    fn classes(names: &[&str]) -> Vec<(String, Option<String>)> {
+        names.iter().map(|name| (name.to_string(), None)).collect()
+    }
+
+    #[test]
+    fn converts_to_snake_case() {
+        assert_eq!(to_snake_case("getUUIDString"), "get_uuid_string");
+        assert_eq!(to_snake_case("tick"), "tick");
+        assert_eq!(to_snake_case("BlockPos"), "block_pos");
+        assert_eq!(to_snake_case("method_1234"), "method_1234");
+        assert_eq!(to_snake_case("getX2D"), "get_x2_d");
+        assert_eq!(to_snake_case("URL"), "url");
+        assert_eq!(to_snake_case("Foo_Bar"), "foo_bar");
+    }
+
+    #[test]
+    fn splits_method_descriptors() {
+        assert_eq!(split_method_desc("()V"), (vec![], "V"));
+        assert_eq!(
+            split_method_desc("(I[[JLjava/lang/String;[Lnet/minecraft/Foo;Z)Lnet/minecraft/Bar;"),
+            (
+                vec![
+                    "I",
+                    "[[J",
+                    "Ljava/lang/String;",
+                    "[Lnet/minecraft/Foo;",
+                    "Z"
+                ],
+                "Lnet/minecraft/Bar;"
+            )
+        );
+        // Malformed descriptors don't panic
+        assert_eq!(split_method_desc("(Ljava/lang"), (vec!["Ljava/lang"], "V"));
+        assert_eq!(split_method_desc("(["), (vec!["["], "V"));
+        assert_eq!(split_method_desc(""), (vec![], "V"));
+    }
+
+    #[test]
+    fn makes_unique_names() {
+        let mut names = HashSet::from(["statics".to_string()]);
+        assert_eq!(unique_name(&mut names, "tick"), "tick");
+        assert_eq!(unique_name(&mut names, "tick"), "tick_1");
+        assert_eq!(unique_name(&mut names, "tick"), "tick_2");
+        assert_eq!(unique_name(&mut names, "statics"), "statics_1");
+        assert_eq!(unique_name(&mut names, "type"), "r#type");
+        assert_eq!(unique_name(&mut names, "type"), "type_1");
+        assert_eq!(unique_name(&mut names, "_"), "__");
+        assert_eq!(unique_name(&mut names, "self"), "self_");
+        assert_eq!(unique_name(&mut names, "self_"), "self__1");
+        assert_eq!(unique_name(&mut names, "super_"), "super_");
+        assert_eq!(unique_name(&mut names, "super"), "super_1");
+    }
+
+    #[test]
+    fn names_wrappers_and_their_members() {
+        let out = generate(&mappings(), "named", &classes(&["net/minecraft/FooBar"])).unwrap();
+        assert!(out.contains("pub struct FooBar<'local>"));
+        assert!(out.contains("static FOO_BAR_CLASS: "));
+        assert!(out.contains("pub fn get_uuid_string(&self, arg0: i32, arg1: &impl "));
+        assert!(out.contains("-> ::mc_connect::bindings::Result<FooBar<'local>>"));
+        assert!(out.contains("pub fn self_(&self)"));
+        assert!(out.contains("pub fn self__1(&self)"));
+        assert!(out.contains("pub fn r#type(&self)"));
+        assert!(out.contains("pub fn set_type(&self, value: i32)"));
+
+        let out = generate(
+            &mappings(),
+            "named",
+            &classes(&["net/minecraft/Outer$Inner"]),
+        )
+        .unwrap();
+        assert!(out.contains("pub struct OuterInner<'local>"));
+        assert!(out.contains("static OUTER_INNER_CLASS: "));
+    }
+
+    #[test]
+    fn refuses_clashing_names() {
+        let clash = |names: &[&str]| match generate(&mappings(), "named", &classes(names)) {
+            Err(Error::DuplicateName(name)) => name,
+            _ => panic!("generated {:?}", names),
+        };
+        assert_eq!(
+            clash(&["net/minecraft/FooBar", "net/minecraft/FooBar"]),
+            "FooBar"
+        );
+        assert_eq!(
+            clash(&["net/minecraft/FooBar", "net/minecraft/Foo_Bar"]),
+            "Foo_Bar"
+        );
+
+        let renamed = vec![
+            ("net/minecraft/FooBar".to_string(), None),
+            (
+                "net/minecraft/Foo_Bar".to_string(),
+                Some("OtherFooBar".to_string()),
+            ),
+        ];
+        assert!(generate(&mappings(), "named", &renamed).is_ok());
+    }
+}
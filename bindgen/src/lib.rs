@@ -0,0 +1,134 @@
+//! Generates typed Rust wrappers of Minecraft classes from Yarn's Tiny mappings or Mojang's
+//! ProGuard ones.
+//!
+//! The wrappers are for plugins running inside the game, and call through
+//! `mc_connect::bindings`, which resolves every member through the agent's mappings and caches
+//! its JNI ID. Generate them from a build script:
+//!
+//! ```ignore
+//! mc_connect_bindgen::Builder::new("mappings/mappings.tiny")
+//!     .class("net/minecraft/client/MinecraftClient")
+//!     .class("net/minecraft/client/network/ClientPlayerEntity")
+//!     .write_to(Path::new(&env::var("OUT_DIR")?).join("minecraft.rs"))?;
+//! ```
+//!
+//! and include them in the plugin:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/minecraft.rs"));
+//!
+//! let ctx = MinecraftContext::from_jvm(jvm)?;
+//! let pos = MinecraftClient::statics(&ctx).get_instance()?.player()?.cast::<Entity>()?.get_pos()?;
+//! ```
+//!
+//! Wrappers use Yarn's names by default; to write plugins against Mojang's, generate them with
+//! the [`MOJMAP`](mappings::MOJMAP) namespace and attach the agent with the same source namespace.
+//!
+//! Only the members a class declares itself are generated, as the mappings don't record
+//! superclasses; [`cast`](mc_connect::bindings::Binding::cast) an object to reach inherited
+//! ones. Members are static or not depending on what they turn out to be at runtime, and
+//! `statics` gives a wrapper to call the static ones through.
+
+mod generate;
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use mc_connect::mappings::{self, Mappings, MappingsError};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Mappings(MappingsError),
+    MissingNamespace(String),
+    UnknownClass(String),
+    /// Two classes would get the same Rust name, or names such as `FooBar` and `Foo_Bar` whose
+    /// statics clash; give one another with [`Builder::class_as`].
+    DuplicateName(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "failed to write bindings: {}", e),
+            Error::Mappings(e) => write!(f, "{}", e),
+            Error::MissingNamespace(namespace) => {
+                write!(f, "mappings have no {} namespace", namespace)
+            }
+            Error::UnknownClass(class_name) => write!(f, "class {} is not mapped", class_name),
+            Error::DuplicateName(name) => write!(f, "more than one class is named like {}", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<MappingsError> for Error {
+    fn from(error: MappingsError) -> Self {
+        Error::Mappings(error)
+    }
+}
+
+pub struct Builder {
+    mappings: PathBuf,
+    mojmap: Option<PathBuf>,
+    namespace: String,
+    classes: Vec<(String, Option<String>)>,
+}
+
+impl Builder {
+    pub fn new(mappings: impl Into<PathBuf>) -> Self {
+        Self {
+            mappings: mappings.into(),
+            mojmap: None,
+            namespace: mappings::NAMED.to_string(),
+            classes: Vec::new(),
+        }
+    }
+
+    /// Joins Mojang's `client.txt` to the mappings, through the obfuscated names both have.
+    pub fn mojmap(mut self, mojmap: impl Into<PathBuf>) -> Self {
+        self.mojmap = Some(mojmap.into());
+        self
+    }
+
+    /// Sets the namespace classes are given in and wrappers are generated with, which the agent
+    /// must resolve names from. Defaults to [`NAMED`](mappings::NAMED).
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// Adds a wrapper of a class by its name, named after its simple name.
+    pub fn class(mut self, class_name: &str) -> Self {
+        self.classes.push((class_name.to_string(), None));
+        self
+    }
+
+    pub fn class_as(mut self, class_name: &str, rust_name: &str) -> Self {
+        self.classes
+            .push((class_name.to_string(), Some(rust_name.to_string())));
+        self
+    }
+
+    pub fn generate(&self) -> Result<String, Error> {
+        let mut mappings = Mappings::read(&self.mappings)?;
+        if let Some(mojmap) = &self.mojmap {
+            mappings = mappings.compose(&Mappings::read(mojmap)?, mappings::OFFICIAL)?;
+        }
+        generate::generate(&mappings, &self.namespace, &self.classes)
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, self.generate()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use std::{env, process::ExitCode};
+
+use mc_connect_bindgen::Builder;
+
+const USAGE: &str = "usage: mc-connect-bindgen [--mojmap <client.txt>] [--namespace <namespace>] \
+                     <mappings.tiny> <output.rs> <class>...";
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let mut mojmap = None;
+    let mut namespace = None;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        let option = match arg.as_str() {
+            "--mojmap" => &mut mojmap,
+            "--namespace" => &mut namespace,
+            _ => {
+                positional.push(arg);
+                continue;
+            }
+        };
+        let Some(value) = args.next() else {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        };
+        *option = Some(value);
+    }
+    let [mappings, output, classes @ ..] = positional.as_slice() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let mut builder = Builder::new(mappings);
+    if let Some(mojmap) = mojmap {
+        builder = builder.mojmap(mojmap);
+    }
+    if let Some(namespace) = namespace {
+        builder = builder.namespace(&namespace);
+    }
+    let builder = classes
+        .iter()
+        .fold(builder, |builder, class_name| builder.class(class_name));
+    match builder.write_to(output) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
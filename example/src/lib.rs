@@ -1,9 +1,31 @@
-use jni::JavaVM;
 use mc_connect::minecraft::MinecraftContext;
+use mc_connect::plugin::{self, PluginResult};
 
-#[unsafe(no_mangle)]
-pub extern "C" fn in_mc(jvm: &JavaVM) {
-    println!("Hello minecraft!");
-    let context = MinecraftContext::from_jvm(jvm).unwrap();
-    println!("Minecraft version: {}", context.version);
+plugin::entry! {
+    fn in_mc(jvm, _payload) {
+        println!("Hello minecraft!");
+        let context = match MinecraftContext::from_jvm(jvm) {
+            Ok(context) => context,
+            Err(e) => return PluginResult::err(e.to_string()),
+        };
+        PluginResult::ok(
+            bincode::encode_to_vec(&context.version, bincode::config::standard()).unwrap(),
+        )
+    }
 }
+
+plugin::register!(|registrar| registrar.register("example:greet", greet));
+
+plugin::handler! {
+    fn greet(_jvm, payload) {
+        let name: String =
+            match bincode::decode_from_slice(payload.as_slice(), bincode::config::standard()) {
+                Ok((name, _)) => name,
+                Err(e) => return PluginResult::err(e.to_string()),
+            };
+        let greeting = format!("Hello {} from minecraft!", name);
+        PluginResult::ok(bincode::encode_to_vec(greeting, bincode::config::standard()).unwrap())
+    }
+}
+
+plugin::on_unload!(|_jvm| println!("Goodbye minecraft!"));
@@ -1,11 +1,11 @@
 use std::env;
 
-use mc_connect::initialization::find_and_connect;
+use mc_connect::{communication::Packet, initialization::find_and_connect};
 
 #[allow(dead_code)]
 #[tokio::main]
 async fn main() {
-    let mut mc = find_and_connect().await;
+    let mc = find_and_connect().await;
     let result = mc
         .toast("Test", "Hello from Rust!")
         .await
@@ -13,16 +13,33 @@ async fn main() {
         .get_result()
         .await;
     println!("Result: {:?}", result);
-    mc.run(
-        env::current_exe()
-            .unwrap()
-            .parent()
-            .unwrap()
-            .join("libexample.so"),
-        "in_mc".to_string(),
-    )
-    .await
-    .unwrap()
-    .get_result()
-    .await;
+    let version = mc
+        .run(
+            env::current_exe()
+                .unwrap()
+                .parent()
+                .unwrap()
+                .join("libexample.so"),
+            "in_mc".to_string(),
+            &(),
+        )
+        .await
+        .unwrap()
+        .get_result()
+        .await
+        .unwrap();
+    if let Packet::Return(version) = version {
+        println!("Minecraft version: {}", version.decode::<String>().unwrap());
+    }
+
+    let greeting = mc
+        .send_custom("example:greet", &"Rust".to_string())
+        .await
+        .unwrap()
+        .get_result()
+        .await
+        .unwrap();
+    if let Packet::Custom(greeting) = greeting {
+        println!("{}", greeting.decode::<String>().unwrap());
+    }
 }
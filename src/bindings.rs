@@ -0,0 +1,1026 @@
+//! Calling into the game from a plugin by name, either through the wrappers generated by
+//! `mc-connect-bindgen` or through the helpers on [`MinecraftContext`].
+//!
+//! Classes and members are named by their names and descriptors in the source namespace, the
+//! one plugins are written against. Each one is looked up once through the agent's mappings, the same way as for an
+//! [`InvokePacket`](crate::communication::InvokePacket), and its JNI ID is cached for the rest
+//! of the game's life.
+//!
+//! Tiny mappings don't say which members are static, so that is decided when a member is
+//! resolved: static members ignore the object they are accessed through, which lets a
+//! wrapper of a null object, such as the one returned by a generated `statics`, reach them.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    hash::Hash,
+    str::FromStr,
+    sync::{LazyLock, Mutex, OnceLock},
+};
+
+use jni::{
+    JNIEnv,
+    objects::{
+        GlobalRef, JClass, JFieldID, JMethodID, JObject, JObjectArray, JStaticFieldID,
+        JStaticMethodID, JString, JThrowable, JValue, JValueOwned,
+    },
+    signature::{JavaType, ReturnType, TypeSignature},
+};
+
+use crate::minecraft::MinecraftContext;
+
+const TOOLS_CLASS: &str = "com/codemob/mcconnect/Tools";
+/// `java.lang.reflect.Modifier.STATIC`
+const STATIC_MODIFIER: i32 = 0x0008;
+
+pub type Result<T> = std::result::Result<T, BindingError>;
+
+#[derive(Debug)]
+pub enum BindingError {
+    Jni(jni::errors::Error),
+    /// A Java exception was thrown, and cleared; holds its `toString()`.
+    Exception(String),
+    ClassNotFound(String),
+    MethodNotFound {
+        class_name: String,
+        name: String,
+        desc: String,
+    },
+    FieldNotFound {
+        class_name: String,
+        name: String,
+        desc: String,
+    },
+    /// An instance member was accessed through a null object.
+    NullPointer {
+        class_name: String,
+        member: String,
+    },
+    /// An instance member was accessed as a static one.
+    NotStatic {
+        class_name: String,
+        member: String,
+    },
+    /// The arguments, or the value of a field, don't match the descriptor.
+    ArgumentMismatch {
+        member: String,
+        desc: String,
+    },
+    /// The object isn't an instance of the class it was cast to.
+    ClassCast {
+        class_name: String,
+    },
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingError::Jni(e) => write!(f, "JNI call failed: {}", e),
+            BindingError::Exception(exception) => write!(f, "Java exception: {}", exception),
+            BindingError::ClassNotFound(class_name) => write!(f, "class {} not found", class_name),
+            BindingError::MethodNotFound {
+                class_name,
+                name,
+                desc,
+            } => write!(f, "method {}.{}{} not found", class_name, name, desc),
+            BindingError::FieldNotFound {
+                class_name,
+                name,
+                desc,
+            } => write!(f, "field {}.{}:{} not found", class_name, name, desc),
+            BindingError::NullPointer { class_name, member } => {
+                write!(f, "accessed {}.{} of a null object", class_name, member)
+            }
+            BindingError::NotStatic { class_name, member } => {
+                write!(f, "{}.{} is not static", class_name, member)
+            }
+            BindingError::ArgumentMismatch { member, desc } => {
+                write!(f, "values given don't match {}: {}", member, desc)
+            }
+            BindingError::ClassCast { class_name } => {
+                write!(f, "object is not an instance of {}", class_name)
+            }
+        }
+    }
+}
+
+impl Error for BindingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BindingError::Jni(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<jni::errors::Error> for BindingError {
+    fn from(error: jni::errors::Error) -> Self {
+        BindingError::Jni(error)
+    }
+}
+
+/// Runs JNI calls, turning a Java exception they throw into [`BindingError::Exception`].
+fn catch<'local, T>(
+    env: &mut JNIEnv<'local>,
+    f: impl FnOnce(&mut JNIEnv<'local>) -> jni::errors::Result<T>,
+) -> Result<T> {
+    match f(env) {
+        Ok(value) => Ok(value),
+        Err(jni::errors::Error::JavaException) => {
+            let exception = take_exception(env)?;
+            Err(describe_exception(env, &exception)?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Clears the pending exception, which has to happen before any other JNI call.
+fn take_exception<'local>(env: &mut JNIEnv<'local>) -> Result<JThrowable<'local>> {
+    let exception = env.exception_occurred()?;
+    env.exception_clear()?;
+    Ok(exception)
+}
+
+fn describe_exception(env: &mut JNIEnv, exception: &JThrowable) -> Result<BindingError> {
+    let string = env
+        .call_method(exception, "toString", "()Ljava/lang/String;", &[])?
+        .l()?;
+    Ok(BindingError::Exception(
+        env.get_string(&JString::from(string))?.into(),
+    ))
+}
+
+/// A Java object along with the env of the thread it belongs to.
+pub struct JavaObject<'local> {
+    env: JNIEnv<'local>,
+    object: JObject<'local>,
+}
+
+impl<'local> JavaObject<'local> {
+    pub fn new(env: &JNIEnv<'local>, object: JObject<'local>) -> Self {
+        Self {
+            // SAFETY: the clone can't outlive the local frame of `env`
+            env: unsafe { env.unsafe_clone() },
+            object,
+        }
+    }
+
+    pub fn null(env: &JNIEnv<'local>) -> Self {
+        Self::new(env, JObject::null())
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.object.is_null()
+    }
+
+    pub fn as_obj(&self) -> &JObject<'local> {
+        &self.object
+    }
+
+    pub fn into_inner(self) -> JObject<'local> {
+        self.object
+    }
+
+    pub fn env(&self) -> JNIEnv<'local> {
+        // SAFETY: see `new`
+        unsafe { self.env.unsafe_clone() }
+    }
+
+    pub fn is_instance<T: Binding<'local>>(&self) -> Result<bool> {
+        let mut env = self.env();
+        let class = T::class().get(&mut env)?;
+        Ok(env.is_instance_of(&self.object, <&JClass>::from(class.as_obj()))?)
+    }
+
+    /// Views the object as a `T`, failing unless it is null or an instance of `T`.
+    pub fn cast<T: Binding<'local>>(self) -> Result<T> {
+        if !self.is_instance::<T>()? {
+            return Err(BindingError::ClassCast {
+                class_name: T::class().name().to_string(),
+            });
+        }
+        // SAFETY: checked above
+        Ok(unsafe { T::from_object(self) })
+    }
+}
+
+impl fmt::Debug for JavaObject<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("JavaObject")
+            .field(&self.object.as_raw())
+            .finish()
+    }
+}
+
+/// A generated wrapper of a Java class.
+pub trait Binding<'local>: Sized {
+    fn class() -> &'static ClassRef;
+
+    /// Wraps `object` without checking its class; see [`JavaObject::cast`].
+    ///
+    /// # Safety
+    ///
+    /// `object` must be null or an instance of [`class`](Self::class), since calls through the
+    /// wrapper assume it is.
+    unsafe fn from_object(object: JavaObject<'local>) -> Self;
+
+    fn as_object(&self) -> &JavaObject<'local>;
+
+    fn into_object(self) -> JavaObject<'local>;
+
+    fn is_null(&self) -> bool {
+        self.as_object().is_null()
+    }
+
+    fn cast<T: Binding<'local>>(self) -> Result<T> {
+        self.into_object().cast()
+    }
+}
+
+static OBJECT_CLASS: ClassRef = ClassRef::new("java/lang/Object");
+
+impl<'local> Binding<'local> for JavaObject<'local> {
+    fn class() -> &'static ClassRef {
+        &OBJECT_CLASS
+    }
+
+    unsafe fn from_object(object: JavaObject<'local>) -> Self {
+        object
+    }
+
+    fn as_object(&self) -> &JavaObject<'local> {
+        self
+    }
+
+    fn into_object(self) -> JavaObject<'local> {
+        self
+    }
+}
+
+/// Converts an argument of a generated method.
+pub trait ToArg<'local> {
+    fn to_arg(&self, env: &mut JNIEnv<'local>) -> Result<JValueOwned<'local>>;
+}
+
+macro_rules! primitive_arg {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl<'local> ToArg<'local> for $ty {
+                fn to_arg(&self, _env: &mut JNIEnv<'local>) -> Result<JValueOwned<'local>> {
+                    Ok(JValueOwned::$variant((*self).into()))
+                }
+            }
+        )*
+    };
+}
+
+primitive_arg! {
+    bool => Bool,
+    i8 => Byte,
+    u16 => Char,
+    i16 => Short,
+    i32 => Int,
+    i64 => Long,
+    f32 => Float,
+    f64 => Double,
+}
+
+impl<'local> ToArg<'local> for &str {
+    fn to_arg(&self, env: &mut JNIEnv<'local>) -> Result<JValueOwned<'local>> {
+        let string = catch(env, |env| env.new_string(self))?;
+        Ok(JValueOwned::Object(string.into()))
+    }
+}
+
+impl<'local, T: Binding<'local>> ToArg<'local> for &T {
+    fn to_arg(&self, env: &mut JNIEnv<'local>) -> Result<JValueOwned<'local>> {
+        Ok(JValueOwned::Object(
+            env.new_local_ref(self.as_object().as_obj())?,
+        ))
+    }
+}
+
+/// Converts the result of a generated method or field.
+pub trait FromJava<'local>: Sized {
+    fn from_java(env: &mut JNIEnv<'local>, value: JValueOwned<'local>) -> Result<Self>;
+}
+
+macro_rules! primitive_result {
+    ($($ty:ty => $getter:ident),* $(,)?) => {
+        $(
+            impl<'local> FromJava<'local> for $ty {
+                fn from_java(_env: &mut JNIEnv<'local>, value: JValueOwned<'local>) -> Result<Self> {
+                    Ok(value.$getter()?)
+                }
+            }
+        )*
+    };
+}
+
+primitive_result! {
+    () => v,
+    bool => z,
+    i8 => b,
+    u16 => c,
+    i16 => s,
+    i32 => i,
+    i64 => j,
+    f32 => f,
+    f64 => d,
+}
+
+impl<'local> FromJava<'local> for Option<String> {
+    fn from_java(env: &mut JNIEnv<'local>, value: JValueOwned<'local>) -> Result<Self> {
+        let string = JString::from(value.l()?);
+        if string.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(catch(env, |env| env.get_string(&string))?.into()))
+    }
+}
+
+impl<'local, T: Binding<'local>> FromJava<'local> for T {
+    fn from_java(env: &mut JNIEnv<'local>, value: JValueOwned<'local>) -> Result<Self> {
+        JavaObject::new(env, value.l()?).cast()
+    }
+}
+
+/// A class by its name in the source namespace, loaded on first use.
+pub struct ClassRef {
+    name: &'static str,
+    class: OnceLock<GlobalRef>,
+}
+
+impl ClassRef {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            class: OnceLock::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn get(&self, env: &mut JNIEnv) -> Result<&GlobalRef> {
+        if let Some(class) = self.class.get() {
+            return Ok(class);
+        }
+        let class = load_class(env, self.name)?;
+        Ok(self.class.get_or_init(|| class))
+    }
+}
+
+/// A method or constructor by its name and descriptor in the source namespace, resolved on first
+/// use.
+pub struct MethodRef {
+    class: &'static ClassRef,
+    name: &'static str,
+    desc: &'static str,
+    method: OnceLock<Method>,
+}
+
+impl MethodRef {
+    pub const fn new(class: &'static ClassRef, name: &'static str, desc: &'static str) -> Self {
+        Self {
+            class,
+            name,
+            desc,
+            method: OnceLock::new(),
+        }
+    }
+
+    fn get(&self, env: &mut JNIEnv) -> Result<&Method> {
+        if let Some(method) = self.method.get() {
+            return Ok(method);
+        }
+        self.class.get(env)?;
+        let method = resolve_method(env, self.class.name(), self.name, self.desc)?;
+        Ok(self.method.get_or_init(|| method))
+    }
+
+    /// Calls the method on `this`, or statically if it is static.
+    pub fn call<'local, R: FromJava<'local>>(
+        &self,
+        this: &JavaObject<'local>,
+        args: &[&dyn ToArg<'local>],
+    ) -> Result<R> {
+        let mut env = this.env();
+        let method = self.get(&mut env)?;
+        let args = to_args(&mut env, args)?;
+        let args = args.iter().map(JValueOwned::borrow).collect::<Vec<_>>();
+        let value = method.call(&mut env, this.as_obj(), &args)?;
+        R::from_java(&mut env, value)
+    }
+
+    /// Creates an object with the constructor, which must be named `<init>`.
+    pub fn construct<'local, T: Binding<'local>>(
+        &self,
+        env: &JNIEnv<'local>,
+        args: &[&dyn ToArg<'local>],
+    ) -> Result<T> {
+        // SAFETY: the clone doesn't outlive this call
+        let mut env = unsafe { env.unsafe_clone() };
+        let method = self.get(&mut env)?;
+        let args = to_args(&mut env, args)?;
+        let args = args.iter().map(JValueOwned::borrow).collect::<Vec<_>>();
+        let object = method.construct(&mut env, &args)?;
+        JavaObject::new(&env, object).cast()
+    }
+}
+
+fn to_args<'local>(
+    env: &mut JNIEnv<'local>,
+    args: &[&dyn ToArg<'local>],
+) -> Result<Vec<JValueOwned<'local>>> {
+    args.iter().map(|arg| arg.to_arg(env)).collect()
+}
+
+/// A field by its name and descriptor in the source namespace, resolved on first use.
+pub struct FieldRef {
+    class: &'static ClassRef,
+    name: &'static str,
+    desc: &'static str,
+    field: OnceLock<Field>,
+}
+
+impl FieldRef {
+    pub const fn new(class: &'static ClassRef, name: &'static str, desc: &'static str) -> Self {
+        Self {
+            class,
+            name,
+            desc,
+            field: OnceLock::new(),
+        }
+    }
+
+    fn get_field(&self, env: &mut JNIEnv) -> Result<&Field> {
+        if let Some(field) = self.field.get() {
+            return Ok(field);
+        }
+        self.class.get(env)?;
+        let field = resolve_field(env, self.class.name(), self.name, self.desc)?;
+        Ok(self.field.get_or_init(|| field))
+    }
+
+    /// Reads the field of `this`, or the static field.
+    pub fn get<'local, R: FromJava<'local>>(&self, this: &JavaObject<'local>) -> Result<R> {
+        let mut env = this.env();
+        let value = self.get_field(&mut env)?.get(&mut env, this.as_obj())?;
+        R::from_java(&mut env, value)
+    }
+
+    /// Writes the field of `this`, or the static field.
+    pub fn set<'local>(&self, this: &JavaObject<'local>, value: &dyn ToArg<'local>) -> Result<()> {
+        let mut env = this.env();
+        let field = self.get_field(&mut env)?;
+        let value = value.to_arg(&mut env)?;
+        field.set(&mut env, this.as_obj(), value.borrow())
+    }
+}
+
+type MemberKey = (String, String, String);
+
+// Resolved names for `MinecraftContext`, which unlike generated code has nowhere to keep them
+static CLASSES: LazyLock<Mutex<HashMap<String, GlobalRef>>> = LazyLock::new(Default::default);
+static METHODS: LazyLock<Mutex<HashMap<MemberKey, Method>>> = LazyLock::new(Default::default);
+static FIELDS: LazyLock<Mutex<HashMap<MemberKey, Field>>> = LazyLock::new(Default::default);
+
+fn cached<K: Eq + Hash, V: Clone>(
+    cache: &Mutex<HashMap<K, V>>,
+    key: K,
+    resolve: impl FnOnce() -> Result<V>,
+) -> Result<V> {
+    if let Some(value) = cache.lock().unwrap().get(&key) {
+        return Ok(value.clone());
+    }
+    // Not holding the lock while calling into Java, which may take a while
+    let value = resolve()?;
+    cache.lock().unwrap().insert(key, value.clone());
+    Ok(value)
+}
+
+/// Calls into the game by names in the source namespace, which are mapped through the agent's
+/// mappings and cached for the rest of the game's life.
+///
+/// Arguments are checked against the descriptor, and objects against the classes of the
+/// receiver, parameters and fields, so a mistake is an error rather than a crash.
+impl<'a> MinecraftContext<'a> {
+    /// Loads a class with the game's class loader.
+    pub fn class(&mut self, class_name: &str) -> Result<JClass<'a>> {
+        let class = cached(&CLASSES, class_name.to_string(), || {
+            load_class(&mut self.env, class_name)
+        })?;
+        Ok(JClass::from(self.env.new_local_ref(class)?))
+    }
+
+    fn method(&mut self, class_name: &str, method_name: &str, desc: &str) -> Result<Method> {
+        self.class(class_name)?;
+        let key = (
+            class_name.to_string(),
+            method_name.to_string(),
+            desc.to_string(),
+        );
+        cached(&METHODS, key, || {
+            resolve_method(&mut self.env, class_name, method_name, desc)
+        })
+    }
+
+    fn field(&mut self, class_name: &str, field_name: &str, desc: &str) -> Result<Field> {
+        self.class(class_name)?;
+        let key = (
+            class_name.to_string(),
+            field_name.to_string(),
+            desc.to_string(),
+        );
+        cached(&FIELDS, key, || {
+            resolve_field(&mut self.env, class_name, field_name, desc)
+        })
+    }
+
+    pub fn call_static(
+        &mut self,
+        class_name: &str,
+        method_name: &str,
+        desc: &str,
+        args: &[JValue],
+    ) -> Result<JValueOwned<'a>> {
+        let method = self.method(class_name, method_name, desc)?;
+        if !method.is_static() {
+            return Err(method.not_static());
+        }
+        method.call(&mut self.env, &JObject::null(), args)
+    }
+
+    /// Calls a method of `object`, declared by `class_name` or one of its superclasses.
+    pub fn call_method(
+        &mut self,
+        object: &JObject,
+        class_name: &str,
+        method_name: &str,
+        desc: &str,
+        args: &[JValue],
+    ) -> Result<JValueOwned<'a>> {
+        let method = self.method(class_name, method_name, desc)?;
+        method.call(&mut self.env, object, args)
+    }
+
+    /// Creates an object with the constructor of `class_name` taking `desc`.
+    pub fn new_object(
+        &mut self,
+        class_name: &str,
+        desc: &str,
+        args: &[JValue],
+    ) -> Result<JObject<'a>> {
+        let method = self.method(class_name, "<init>", desc)?;
+        method.construct(&mut self.env, args)
+    }
+
+    pub fn get_static_field(
+        &mut self,
+        class_name: &str,
+        field_name: &str,
+        desc: &str,
+    ) -> Result<JValueOwned<'a>> {
+        let field = self.field(class_name, field_name, desc)?;
+        if !field.is_static() {
+            return Err(field.not_static());
+        }
+        field.get(&mut self.env, &JObject::null())
+    }
+
+    pub fn get_field(
+        &mut self,
+        object: &JObject,
+        class_name: &str,
+        field_name: &str,
+        desc: &str,
+    ) -> Result<JValueOwned<'a>> {
+        let field = self.field(class_name, field_name, desc)?;
+        field.get(&mut self.env, object)
+    }
+
+    pub fn set_static_field(
+        &mut self,
+        class_name: &str,
+        field_name: &str,
+        desc: &str,
+        value: JValue,
+    ) -> Result<()> {
+        let field = self.field(class_name, field_name, desc)?;
+        if !field.is_static() {
+            return Err(field.not_static());
+        }
+        field.set(&mut self.env, &JObject::null(), value)
+    }
+
+    pub fn set_field(
+        &mut self,
+        object: &JObject,
+        class_name: &str,
+        field_name: &str,
+        desc: &str,
+        value: JValue,
+    ) -> Result<()> {
+        let field = self.field(class_name, field_name, desc)?;
+        field.set(&mut self.env, object, value)
+    }
+}
+
+fn load_class(env: &mut JNIEnv, class_name: &str) -> Result<GlobalRef> {
+    let class = catch_lookup(
+        env,
+        |env| {
+            let name = env.new_string(class_name)?;
+            let class = env
+                .call_static_method(
+                    TOOLS_CLASS,
+                    "loadClass",
+                    "(Ljava/lang/String;)Ljava/lang/Class;",
+                    &[(&name).into()],
+                )?
+                .l()?;
+            env.new_global_ref(class)
+        },
+        "java/lang/ClassNotFoundException",
+        || BindingError::ClassNotFound(class_name.to_string()),
+    )?;
+    Ok(class)
+}
+
+/// Like [`catch`], but reports an exception of class `not_found` as the error from `error`.
+fn catch_lookup<'local, T>(
+    env: &mut JNIEnv<'local>,
+    f: impl FnOnce(&mut JNIEnv<'local>) -> jni::errors::Result<T>,
+    not_found: &str,
+    error: impl FnOnce() -> BindingError,
+) -> Result<T> {
+    match f(env) {
+        Err(jni::errors::Error::JavaException) => {
+            let exception = take_exception(env)?;
+            if env.is_instance_of(&exception, not_found)? {
+                Err(error())
+            } else {
+                Err(describe_exception(env, &exception)?)
+            }
+        }
+        result => catch(env, |_| result),
+    }
+}
+
+/// Looks up a member with `Tools` and returns it with whether it is static, and the class
+/// declaring it.
+fn lookup_member<'local>(
+    env: &mut JNIEnv<'local>,
+    tool: &str,
+    tool_desc: &str,
+    [class_name, name, desc]: [&str; 3],
+    not_found: &str,
+    error: impl FnOnce() -> BindingError,
+) -> Result<(JObject<'local>, bool, GlobalRef)> {
+    let member = catch_lookup(
+        env,
+        |env| {
+            let class_name = env.new_string(class_name)?;
+            let name = env.new_string(name)?;
+            let desc = env.new_string(desc)?;
+            env.call_static_method(
+                TOOLS_CLASS,
+                tool,
+                tool_desc,
+                &[(&class_name).into(), (&name).into(), (&desc).into()],
+            )?
+            .l()
+        },
+        not_found,
+        error,
+    )?;
+    let modifiers = env.call_method(&member, "getModifiers", "()I", &[])?.i()?;
+    let class = env
+        .call_method(&member, "getDeclaringClass", "()Ljava/lang/Class;", &[])?
+        .l()?;
+    let class = env.new_global_ref(class)?;
+    Ok((member, modifiers & STATIC_MODIFIER != 0, class))
+}
+
+#[derive(Clone, Copy)]
+enum MethodId {
+    Static(JStaticMethodID),
+    Instance(JMethodID),
+}
+
+#[derive(Clone)]
+struct Method {
+    class_name: String,
+    name: String,
+    /// The class declaring the method.
+    class: GlobalRef,
+    id: MethodId,
+    signature: TypeSignature,
+    /// Class of each parameter that takes an object.
+    params: Vec<Option<GlobalRef>>,
+}
+
+fn resolve_method(env: &mut JNIEnv, class_name: &str, name: &str, desc: &str) -> Result<Method> {
+    let signature = TypeSignature::from_str(desc)?;
+    let (method, is_static, class) = lookup_member(
+        env,
+        "lookupMethod",
+        "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)Ljava/lang/reflect/Executable;",
+        [class_name, name, desc],
+        "java/lang/NoSuchMethodException",
+        || BindingError::MethodNotFound {
+            class_name: class_name.to_string(),
+            name: name.to_string(),
+            desc: desc.to_string(),
+        },
+    )?;
+    let raw_env = env.get_raw();
+    // SAFETY: `method` is a live `Method` or `Constructor`, which always has an ID
+    let id = unsafe {
+        let from_reflected =
+            (**raw_env)
+                .FromReflectedMethod
+                .ok_or(jni::errors::Error::JNIEnvMethodNotFound(
+                    "FromReflectedMethod",
+                ))?;
+        from_reflected(raw_env, method.as_raw())
+    };
+    let id = if is_static {
+        MethodId::Static(unsafe { JStaticMethodID::from_raw(id) })
+    } else {
+        MethodId::Instance(unsafe { JMethodID::from_raw(id) })
+    };
+    let param_classes: JObjectArray = catch(env, |env| {
+        env.call_method(&method, "getParameterTypes", "()[Ljava/lang/Class;", &[])?
+            .l()
+    })?
+    .into();
+    let params = signature
+        .args
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| match ty {
+            JavaType::Primitive(_) => Ok(None),
+            _ => {
+                let class = env.get_object_array_element(&param_classes, i as i32)?;
+                Ok(Some(env.new_global_ref(class)?))
+            }
+        })
+        .collect::<Result<_>>()?;
+    Ok(Method {
+        class_name: class_name.to_string(),
+        name: name.to_string(),
+        class,
+        id,
+        signature,
+        params,
+    })
+}
+
+impl Method {
+    fn is_static(&self) -> bool {
+        matches!(self.id, MethodId::Static(_))
+    }
+
+    fn not_static(&self) -> BindingError {
+        BindingError::NotStatic {
+            class_name: self.class_name.clone(),
+            member: self.name.clone(),
+        }
+    }
+
+    fn check_args(&self, env: &mut JNIEnv, args: &[JValue]) -> Result<()> {
+        let params = &self.signature.args;
+        let mut fit = params.len() == args.len();
+        for ((ty, class), arg) in params.iter().zip(&self.params).zip(args) {
+            fit = fit && fits(env, ty, class.as_ref(), arg)?;
+        }
+        if !fit {
+            return Err(BindingError::ArgumentMismatch {
+                member: format!("{}.{}", self.class_name, self.name),
+                desc: self.signature.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Calls the method on `this`, or statically if it is static.
+    fn call<'local>(
+        &self,
+        env: &mut JNIEnv<'local>,
+        this: &JObject,
+        args: &[JValue],
+    ) -> Result<JValueOwned<'local>> {
+        self.check_args(env, args)?;
+        let args = args.iter().map(JValue::as_jni).collect::<Vec<_>>();
+        let ret = self.signature.ret.clone();
+        match self.id {
+            // SAFETY: the arguments, and the object the method is called on, were checked
+            // against the method's signature and class
+            MethodId::Static(id) => catch(env, |env| unsafe {
+                env.call_static_method_unchecked(
+                    <&JClass>::from(self.class.as_obj()),
+                    id,
+                    ret,
+                    &args,
+                )
+            }),
+            MethodId::Instance(_) if this.is_null() => Err(BindingError::NullPointer {
+                class_name: self.class_name.clone(),
+                member: self.name.clone(),
+            }),
+            MethodId::Instance(id) => {
+                check_receiver(env, &self.class, &self.class_name, this)?;
+                catch(env, |env| unsafe {
+                    env.call_method_unchecked(this, id, ret, &args)
+                })
+            }
+        }
+    }
+
+    fn construct<'local>(
+        &self,
+        env: &mut JNIEnv<'local>,
+        args: &[JValue],
+    ) -> Result<JObject<'local>> {
+        self.check_args(env, args)?;
+        let args = args.iter().map(JValue::as_jni).collect::<Vec<_>>();
+        let MethodId::Instance(id) = self.id else {
+            unreachable!("constructors aren't static");
+        };
+        // SAFETY: the arguments were checked against the constructor's signature
+        catch(env, |env| unsafe {
+            env.new_object_unchecked(<&JClass>::from(self.class.as_obj()), id, &args)
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FieldId {
+    Static(JStaticFieldID),
+    Instance(JFieldID),
+}
+
+#[derive(Clone)]
+struct Field {
+    class_name: String,
+    name: String,
+    class: GlobalRef,
+    id: FieldId,
+    ty: JavaType,
+    /// Class of the field, if it holds an object.
+    value_class: Option<GlobalRef>,
+}
+
+fn resolve_field(env: &mut JNIEnv, class_name: &str, name: &str, desc: &str) -> Result<Field> {
+    let ty = JavaType::from_str(desc)?;
+    let (field, is_static, class) = lookup_member(
+        env,
+        "lookupField",
+        "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)Ljava/lang/reflect/Field;",
+        [class_name, name, desc],
+        "java/lang/NoSuchFieldException",
+        || BindingError::FieldNotFound {
+            class_name: class_name.to_string(),
+            name: name.to_string(),
+            desc: desc.to_string(),
+        },
+    )?;
+    let raw_env = env.get_raw();
+    // SAFETY: `field` is a live `Field`, which always has an ID
+    let id = unsafe {
+        let from_reflected =
+            (**raw_env)
+                .FromReflectedField
+                .ok_or(jni::errors::Error::JNIEnvMethodNotFound(
+                    "FromReflectedField",
+                ))?;
+        from_reflected(raw_env, field.as_raw())
+    };
+    let id = if is_static {
+        FieldId::Static(unsafe { JStaticFieldID::from_raw(id) })
+    } else {
+        FieldId::Instance(unsafe { JFieldID::from_raw(id) })
+    };
+    let value_class = match ty {
+        JavaType::Primitive(_) => None,
+        _ => {
+            let value_class = catch(env, |env| {
+                env.call_method(&field, "getType", "()Ljava/lang/Class;", &[])?
+                    .l()
+            })?;
+            Some(env.new_global_ref(value_class)?)
+        }
+    };
+    Ok(Field {
+        class_name: class_name.to_string(),
+        name: name.to_string(),
+        class,
+        id,
+        ty,
+        value_class,
+    })
+}
+
+impl Field {
+    fn is_static(&self) -> bool {
+        matches!(self.id, FieldId::Static(_))
+    }
+
+    fn not_static(&self) -> BindingError {
+        BindingError::NotStatic {
+            class_name: self.class_name.clone(),
+            member: self.name.clone(),
+        }
+    }
+
+    fn null_pointer(&self) -> BindingError {
+        BindingError::NullPointer {
+            class_name: self.class_name.clone(),
+            member: self.name.clone(),
+        }
+    }
+
+    /// Reads the field of `this`, or the static field.
+    fn get<'local>(&self, env: &mut JNIEnv<'local>, this: &JObject) -> Result<JValueOwned<'local>> {
+        match self.id {
+            FieldId::Static(id) => catch(env, |env| {
+                env.get_static_field_unchecked(
+                    <&JClass>::from(self.class.as_obj()),
+                    id,
+                    self.ty.clone(),
+                )
+            }),
+            FieldId::Instance(_) if this.is_null() => Err(self.null_pointer()),
+            FieldId::Instance(id) => {
+                check_receiver(env, &self.class, &self.class_name, this)?;
+                catch(env, |env| {
+                    env.get_field_unchecked(this, id, return_type(&self.ty))
+                })
+            }
+        }
+    }
+
+    /// Writes the field of `this`, or the static field.
+    fn set(&self, env: &mut JNIEnv, this: &JObject, value: JValue) -> Result<()> {
+        if !fits(env, &self.ty, self.value_class.as_ref(), &value)? {
+            return Err(BindingError::ArgumentMismatch {
+                member: format!("{}.{}", self.class_name, self.name),
+                desc: self.ty.to_string(),
+            });
+        }
+        match self.id {
+            FieldId::Static(id) => catch(env, |env| {
+                env.set_static_field(<&JClass>::from(self.class.as_obj()), id, value)
+            }),
+            FieldId::Instance(_) if this.is_null() => Err(self.null_pointer()),
+            FieldId::Instance(id) => {
+                check_receiver(env, &self.class, &self.class_name, this)?;
+                catch(env, |env| env.set_field_unchecked(this, id, value))
+            }
+        }
+    }
+}
+
+/// Whether `value` can be passed as a `ty`, an object of `class`, without the JVM misreading it.
+fn fits(
+    env: &mut JNIEnv,
+    ty: &JavaType,
+    class: Option<&GlobalRef>,
+    value: &JValue,
+) -> Result<bool> {
+    Ok(match (ty, value) {
+        (JavaType::Primitive(primitive), value) => value.primitive_type() == Some(*primitive),
+        (JavaType::Object(_) | JavaType::Array(_), JValue::Object(object)) => match class {
+            Some(class) => env.is_instance_of(*object, <&JClass>::from(class.as_obj()))?,
+            None => false,
+        },
+        _ => false,
+    })
+}
+
+/// Fails unless `this` is an instance of `class`, the class declaring a member.
+fn check_receiver(
+    env: &mut JNIEnv,
+    class: &GlobalRef,
+    class_name: &str,
+    this: &JObject,
+) -> Result<()> {
+    if !env.is_instance_of(this, <&JClass>::from(class.as_obj()))? {
+        return Err(BindingError::ClassCast {
+            class_name: class_name.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn return_type(ty: &JavaType) -> ReturnType {
+    match ty {
+        JavaType::Primitive(primitive) => ReturnType::Primitive(*primitive),
+        JavaType::Array(_) => ReturnType::Array,
+        JavaType::Object(_) | JavaType::Method(_) => ReturnType::Object,
+    }
+}
@@ -1,82 +1,228 @@
-use std::{collections::HashMap, io::ErrorKind, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    sync::{
+        Arc, Mutex as StdMutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use futures::{SinkExt, StreamExt};
+use tokio::sync::{Mutex, broadcast, oneshot};
 
-use tokio::{
-    net::{TcpStream, tcp},
-    sync::{Mutex, oneshot},
+use super::{
+    DEFAULT_TIMEOUT, Event, Packet, PacketHeader, PacketSendResult, RequestError, WelcomePacket,
+    codec::{MalformedPacket, PacketCodec, PacketSink, PacketStream, Transport},
+    handshake,
+    recording::{Direction, Recorder, RecorderSlot},
 };
 
-use super::{Packet, PacketHeader, PacketSendResult};
+/// Number of events buffered for each receiver before older ones are dropped.
+const EVENT_CAPACITY: usize = 64;
 
+/// Requests awaiting a response, keyed by packet id. `None` once the connection has closed.
+pub(crate) type WaitingPackets =
+    Arc<StdMutex<Option<HashMap<u64, oneshot::Sender<Result<Packet, RequestError>>>>>>;
+
+/// A handle to a connection to the agent.
+///
+/// Clones share the connection, so several tasks can send requests over it concurrently.
+#[derive(Clone)]
 pub struct ClientPacketManager {
-    num_packets: u64,
-    pub stream_read: Arc<Mutex<tcp::OwnedReadHalf>>,
-    pub stream_write: Arc<Mutex<tcp::OwnedWriteHalf>>,
-    waiting_packets: Arc<Mutex<HashMap<u64, oneshot::Sender<Packet>>>>,
+    num_packets: Arc<AtomicU64>,
+    default_timeout: Arc<StdMutex<Option<Duration>>>,
+    pub stream_read: Arc<Mutex<PacketStream>>,
+    pub stream_write: Arc<Mutex<PacketSink>>,
+    waiting_packets: WaitingPackets,
+    events: broadcast::Sender<Event>,
+    welcome: WelcomePacket,
+    recorder: RecorderSlot,
 }
 
 impl ClientPacketManager {
-    pub fn new(stream: TcpStream) -> Self {
-        let (read, write) = stream.into_split();
+    /// Performs the handshake over `stream` and starts listening for responses.
+    pub async fn new<T: Transport>(stream: T) -> std::io::Result<Self> {
+        Self::with_codec(stream, PacketCodec::new()).await
+    }
+
+    pub async fn with_codec<T: Transport>(stream: T, codec: PacketCodec) -> std::io::Result<Self> {
+        let mut framed = codec.framed(stream);
+        let welcome = handshake::connect(&mut framed).await?;
+        let (write, read) = framed.split();
         let this = Self {
-            num_packets: 0,
+            num_packets: Arc::new(AtomicU64::new(0)),
+            default_timeout: Arc::new(StdMutex::new(Some(DEFAULT_TIMEOUT))),
             stream_read: Arc::new(Mutex::new(read)),
             stream_write: Arc::new(Mutex::new(write)),
-            waiting_packets: Arc::new(Mutex::new(HashMap::new())),
+            waiting_packets: Arc::new(StdMutex::new(Some(HashMap::new()))),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            welcome,
+            recorder: RecorderSlot::default(),
         };
         this.start_listening();
-        this
+        Ok(this)
+    }
+
+    /// The connection settings the agent chose during the handshake.
+    pub fn welcome(&self) -> &WelcomePacket {
+        &self.welcome
+    }
+
+    /// Sets the timeout used by requests that don't specify their own. `None` waits forever.
+    ///
+    /// Applies to every clone of this handle.
+    pub fn set_default_timeout(&self, timeout: Option<Duration>) {
+        *self.default_timeout.lock().unwrap() = timeout;
+    }
+
+    /// Receives the events of every kind this connection is subscribed to.
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Records every packet sent or received from now on, or stops recording with `None`.
+    pub fn set_recorder(&self, recorder: Option<Arc<Recorder>>) {
+        self.recorder.set(recorder);
     }
 
     pub fn start_listening(&self) {
+        let recorder = self.recorder.clone();
         let stream_read = Arc::clone(&self.stream_read);
         let waiting_packets = Arc::clone(&self.waiting_packets);
+        let events = self.events.clone();
         tokio::spawn(async move {
-            loop {
-                let mut read_guard = stream_read.lock().await;
-                match PacketHeader::read(&mut *read_guard).await {
-                    Ok(packet) => {
+            let mut read_guard = stream_read.lock().await;
+            while let Some(frame) = read_guard.next().await {
+                match frame {
+                    Ok(Ok(packet)) => {
+                        recorder.record(Direction::Received, &packet);
                         if packet.target_id != 0 {
-                            if let Some(sender) =
-                                waiting_packets.lock().await.remove(&packet.target_id)
-                            {
-                                sender.send(packet.packet).unwrap_or_else(|_| {
+                            let sender = waiting_packets
+                                .lock()
+                                .unwrap()
+                                .as_mut()
+                                .and_then(|waiting| waiting.remove(&packet.target_id));
+                            if let Some(sender) = sender {
+                                sender.send(Ok(packet.packet)).unwrap_or_else(|_| {
                                     eprintln!("Failed to send packet result");
                                 });
                             } else {
                                 eprintln!("No sender found for packet ID: {}", packet.target_id);
                             }
+                        } else if let Packet::Event(event) = packet.packet {
+                            // Nobody listening for events is fine
+                            let _ = events.send(event);
                         } else {
                             eprintln!("Invalid packet recieved: {:?}", packet);
                         }
                     }
-                    Err(e) => {
-                        if e.kind() == ErrorKind::UnexpectedEof {
+                    Ok(Err(e)) => {
+                        // Without the request's id its waiter can't be told, so every request
+                        // is failed by disconnecting instead
+                        let Some(target_id) =
+                            MalformedPacket::of(&e).and_then(|malformed| malformed.target_id)
+                        else {
                             break;
-                        } else {
-                            eprintln!("Error receiving packet: {}", e);
+                        };
+                        let sender = waiting_packets
+                            .lock()
+                            .unwrap()
+                            .as_mut()
+                            .and_then(|waiting| waiting.remove(&target_id));
+                        // A malformed event has no waiter and is dropped
+                        if let Some(sender) = sender {
+                            let _ =
+                                sender.send(Err(RequestError::MalformedResponse(e.to_string())));
                         }
                     }
+                    Err(e) => {
+                        eprintln!("Error receiving packet: {}", e);
+                        break;
+                    }
                 }
             }
+            // Dropping the senders fails every outstanding request with `Disconnected`.
+            waiting_packets.lock().unwrap().take();
         });
     }
 
-    pub async fn send_packet(
-        &mut self,
-        packet: &PacketHeader,
-    ) -> std::io::Result<PacketSendResult> {
-        let mut guard = self.stream_write.lock().await;
-        packet.write(&mut *guard).await?;
-        self.num_packets += 1;
-        // Create a oneshot channel to receive the response
+    pub async fn send_packet(&self, packet: &PacketHeader) -> std::io::Result<PacketSendResult> {
+        // Id 0 is never used, it marks packets that aren't responses
+        let id = self.num_packets.fetch_add(1, Ordering::Relaxed) + 1;
+        // Register the response channel before sending so a fast reply can't be missed
         let (tx, rx) = oneshot::channel();
         self.waiting_packets
             .lock()
-            .await
-            .insert(self.num_packets, tx);
-        Ok(PacketSendResult {
-            id: self.num_packets,
+            .unwrap()
+            .as_mut()
+            .ok_or(ErrorKind::NotConnected)?
+            .insert(id, tx);
+        let result = PacketSendResult {
+            id,
             result: rx,
-        })
+            timeout: *self.default_timeout.lock().unwrap(),
+            waiting_packets: Arc::clone(&self.waiting_packets),
+        };
+
+        let packet = PacketHeader {
+            id,
+            ..packet.clone()
+        };
+        self.recorder.record(Direction::Sent, &packet);
+        self.stream_write.lock().await.send(packet).await?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::communication::handshake;
+
+    /// A client connected to an agent that answers its first request with `response`.
+    async fn answered_with(response: &[u8]) -> Result<Packet, RequestError> {
+        let (client, server) = tokio::io::duplex(1024);
+        let agent = async {
+            let mut framed = PacketCodec::new().framed(server);
+            handshake::accept(&mut framed, "named").await.unwrap();
+            framed.next().await.unwrap().unwrap().unwrap();
+            // Once compression is negotiated every frame starts with whether it is compressed
+            let flag = framed.codec().compression().map(|_| 0u8);
+            let mut frame = BytesMut::new();
+            frame.put_u32((response.len() + flag.iter().len()) as u32);
+            frame.put_slice(flag.as_slice());
+            frame.put_slice(response);
+            framed.get_mut().write_all(&frame).await.unwrap();
+            framed
+        };
+        let client = async {
+            let client = ClientPacketManager::new(client).await.unwrap();
+            let request = client
+                .send_packet(&Packet::Confirmation.as_header())
+                .await
+                .unwrap();
+            request.get_result().await
+        };
+        let (_agent, result) = tokio::join!(agent, client);
+        result
+    }
+
+    #[tokio::test]
+    async fn fails_the_request_a_malformed_response_answers() {
+        let config = bincode::config::standard();
+        let mut response = bincode::encode_to_vec((1u64, 1u64), config).unwrap();
+        response.push(u8::MAX);
+        let result = answered_with(&response).await;
+        assert!(matches!(result, Err(RequestError::MalformedResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn disconnects_on_a_response_without_ids() {
+        let result = answered_with(&[u8::MAX]).await;
+        assert_eq!(result, Err(RequestError::Disconnected));
     }
 }
@@ -0,0 +1,451 @@
+use std::{error::Error, fmt, io};
+
+use bincode::{Decode, Encode};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::stream::{SplitSink, SplitStream};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed, LengthDelimitedCodec};
+
+use super::{
+    PacketHeader,
+    compression::{Compression, DEFAULT_COMPRESSION_THRESHOLD},
+};
+
+/// Largest frame accepted or sent unless configured otherwise.
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Leading byte of a frame once compression has been negotiated.
+const FRAME_UNCOMPRESSED: u8 = 0;
+const FRAME_COMPRESSED: u8 = 1;
+
+/// Why a frame that was read whole couldn't be decoded, carried by the `io::Error` the codec
+/// yields for it.
+#[derive(Debug)]
+pub struct MalformedPacket {
+    /// Id of the packet, if the start of the frame could still be read.
+    pub id: Option<u64>,
+    /// Id of the request the packet responds to, 0 if it isn't a response, if it could be read
+    /// along with `id`.
+    pub target_id: Option<u64>,
+    error: Box<dyn Error + Send + Sync>,
+}
+
+impl MalformedPacket {
+    /// The malformed packet `error` is about, if it is about one.
+    pub fn of(error: &io::Error) -> Option<&MalformedPacket> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for MalformedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed packet: {}", self.error)
+    }
+}
+
+impl Error for MalformedPacket {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+fn malformed(
+    ids: (Option<u64>, Option<u64>),
+    error: impl Into<Box<dyn Error + Send + Sync>>,
+) -> io::Error {
+    let (id, target_id) = ids;
+    let error = error.into();
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        MalformedPacket {
+            id,
+            target_id,
+            error,
+        },
+    )
+}
+
+/// The ids of a JSON packet, read when the rest of it can't be.
+#[derive(Deserialize)]
+struct JsonIds {
+    id: u64,
+    target_id: Option<u64>,
+}
+
+/// How packets are serialized on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum Encoding {
+    /// Length-delimited bincode frames.
+    Bincode,
+    /// One JSON object per line, readable in packet dumps and easy to drive from scripts.
+    Json,
+}
+
+/// A byte stream packets can be exchanged over, such as a `TcpStream` or an in-memory duplex.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static> Transport for T {}
+
+pub type PacketFramed = Framed<Box<dyn Transport>, PacketCodec>;
+pub type PacketSink = SplitSink<PacketFramed, PacketHeader>;
+pub type PacketStream = SplitStream<PacketFramed>;
+
+/// Framing for [`PacketHeader`]s in either [`Encoding`].
+///
+/// The encoding is fixed by the first frame sent or received: a codec without one set
+/// detects it from the first byte it decodes, so an agent can serve both kinds of client.
+///
+/// Decoding yields `Ok(Err(_))` for a frame whose payload can't be decoded, holding a
+/// [`MalformedPacket`]; the frame is consumed and the stream stays usable. An outer `Err`
+/// means the framing itself is broken (for example a frame over the maximum length) and the
+/// connection should be closed.
+///
+/// Once a [`Compression`] has been negotiated every bincode frame starts with a flag byte,
+/// and packets of at least the compression threshold are sent compressed.
+#[derive(Debug, Clone)]
+pub struct PacketCodec {
+    frames: LengthDelimitedCodec,
+    encoding: Option<Encoding>,
+    compression: Option<Compression>,
+    compression_threshold: usize,
+    /// How far a partial JSON line has already been searched for its end.
+    json_scanned: usize,
+}
+
+impl PacketCodec {
+    pub fn new() -> Self {
+        Self::with_max_frame_length(DEFAULT_MAX_FRAME_LENGTH)
+    }
+
+    pub fn with_max_frame_length(max_frame_length: usize) -> Self {
+        Self {
+            frames: LengthDelimitedCodec::builder()
+                .max_frame_length(max_frame_length)
+                .new_codec(),
+            encoding: None,
+            compression: None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            json_scanned: 0,
+        }
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    pub fn compression_threshold(mut self, compression_threshold: usize) -> Self {
+        self.compression_threshold = compression_threshold;
+        self
+    }
+
+    pub fn max_frame_length(&self) -> usize {
+        self.frames.max_frame_length()
+    }
+
+    /// The encoding in use, or `None` if no frame has been sent or received yet.
+    pub fn current_encoding(&self) -> Option<Encoding> {
+        self.encoding
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    /// Switches compression on or off; both sides must do this at the same point in the stream.
+    pub(crate) fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
+    }
+
+    pub fn framed<T: Transport>(self, transport: T) -> PacketFramed {
+        Framed::new(Box::new(transport), self)
+    }
+
+    fn decode_json(&mut self, src: &mut BytesMut) -> io::Result<Option<io::Result<PacketHeader>>> {
+        loop {
+            let end = src[self.json_scanned..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map(|end| self.json_scanned + end);
+            // A whole line may arrive in one read
+            if end.unwrap_or(src.len()) > self.max_frame_length() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "JSON line exceeds the maximum frame length",
+                ));
+            }
+            let Some(end) = end else {
+                self.json_scanned = src.len();
+                return Ok(None);
+            };
+            self.json_scanned = 0;
+            let line = src.split_to(end + 1);
+            let line = line.trim_ascii();
+            if !line.is_empty() {
+                return Ok(Some(serde_json::from_slice(line).map_err(|e| {
+                    let ids = serde_json::from_slice::<JsonIds>(line)
+                        .map_or((None, None), |ids| (Some(ids.id), ids.target_id));
+                    malformed(ids, e)
+                })));
+            }
+        }
+    }
+
+    fn decode_payload(&self, frame: &[u8]) -> io::Result<PacketHeader> {
+        let decompressed;
+        let payload = match (self.compression, frame.split_first()) {
+            (None, _) => frame,
+            (Some(_), Some((&FRAME_UNCOMPRESSED, payload))) => payload,
+            (Some(compression), Some((&FRAME_COMPRESSED, payload))) => {
+                decompressed = compression
+                    .decompress(payload, self.max_frame_length())
+                    .map_err(|e| malformed((None, None), e))?;
+                &decompressed
+            }
+            (Some(_), _) => return Err(malformed((None, None), "invalid compression flag")),
+        };
+        bincode::decode_from_slice(payload, bincode::config::standard())
+            .map(|(packet, _)| packet)
+            .map_err(|e| {
+                // The ids come first, so they survive a packet this side doesn't know
+                let config = bincode::config::standard();
+                let ids = match bincode::decode_from_slice::<u64, _>(payload, config) {
+                    Ok((id, read)) => {
+                        let target_id =
+                            bincode::decode_from_slice::<u64, _>(&payload[read..], config);
+                        (Some(id), target_id.ok().map(|(target_id, _)| target_id))
+                    }
+                    Err(_) => (None, None),
+                };
+                malformed(ids, e)
+            })
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = io::Result<PacketHeader>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None if src.is_empty() => return Ok(None),
+            // A length prefix never starts with `{` unless the frame is gigabytes long
+            None if src.chunk()[0] == b'{' => *self.encoding.insert(Encoding::Json),
+            None => *self.encoding.insert(Encoding::Bincode),
+        };
+        if encoding == Encoding::Json {
+            return self.decode_json(src);
+        }
+        let Some(frame) = self.frames.decode(src)? else {
+            return Ok(None);
+        };
+        Ok(Some(self.decode_payload(&frame)))
+    }
+}
+
+impl Encoder<PacketHeader> for PacketCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: PacketHeader, dst: &mut BytesMut) -> io::Result<()> {
+        if *self.encoding.get_or_insert(Encoding::Bincode) == Encoding::Json {
+            let json = serde_json::to_vec(&packet)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            if json.len() > self.max_frame_length() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "packet exceeds the maximum frame length",
+                ));
+            }
+            dst.reserve(json.len() + 1);
+            dst.put_slice(&json);
+            dst.put_u8(b'\n');
+            return Ok(());
+        }
+
+        let bytes = bincode::encode_to_vec(&packet, bincode::config::standard())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let Some(compression) = self.compression else {
+            return self.frames.encode(Bytes::from(bytes), dst);
+        };
+        // The receiver refuses to decompress anything over the maximum frame length
+        if bytes.len() > self.max_frame_length() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet exceeds the maximum frame length",
+            ));
+        }
+
+        let compressed = if bytes.len() >= self.compression_threshold {
+            Some(compression.compress(&bytes)?).filter(|compressed| compressed.len() < bytes.len())
+        } else {
+            None
+        };
+        let (flag, payload) = match &compressed {
+            Some(compressed) => (FRAME_COMPRESSED, compressed),
+            None => (FRAME_UNCOMPRESSED, &bytes),
+        };
+        let mut frame = BytesMut::with_capacity(payload.len() + 1);
+        frame.put_u8(flag);
+        frame.put_slice(payload);
+        self.frames.encode(frame.freeze(), dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+
+    use super::*;
+    use crate::communication::{Packet, PrintPacket};
+
+    fn header(id: u64, target_id: u64, packet: Packet) -> PacketHeader {
+        PacketHeader {
+            id,
+            target_id,
+            packet,
+        }
+    }
+
+    fn print(message: &str) -> Packet {
+        PrintPacket::new(message.to_string())
+    }
+
+    fn encode(codec: &mut PacketCodec, packets: &[PacketHeader]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        for packet in packets {
+            codec.encode(packet.clone(), &mut buf).unwrap();
+        }
+        buf
+    }
+
+    fn decode(codec: &mut PacketCodec, buf: &mut BytesMut) -> Option<PacketHeader> {
+        codec.decode(buf).unwrap().map(Result::unwrap)
+    }
+
+    /// A frame whose ids are intact but whose packet isn't one this side knows.
+    fn unknown_packet(id: u64, target_id: u64) -> BytesMut {
+        let config = bincode::config::standard();
+        let mut payload = bincode::encode_to_vec((id, target_id), config).unwrap();
+        payload.push(u8::MAX);
+        let mut buf = BytesMut::new();
+        LengthDelimitedCodec::new()
+            .encode(Bytes::from(payload), &mut buf)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn decodes_pipelined_frames_in_order() {
+        for encoding in [Encoding::Bincode, Encoding::Json] {
+            let packets = [header(1, 0, print("a")), header(2, 0, print("b"))];
+            let mut buf = encode(&mut PacketCodec::new().encoding(encoding), &packets);
+            let mut codec = PacketCodec::new();
+            assert_eq!(decode(&mut codec, &mut buf), Some(packets[0].clone()));
+            assert_eq!(decode(&mut codec, &mut buf), Some(packets[1].clone()));
+            assert_eq!(decode(&mut codec, &mut buf), None);
+            assert_eq!(codec.current_encoding(), Some(encoding));
+        }
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_partial_frame() {
+        for encoding in [Encoding::Bincode, Encoding::Json] {
+            let packet = header(1, 0, print("partial"));
+            let bytes = encode(
+                &mut PacketCodec::new().encoding(encoding),
+                std::slice::from_ref(&packet),
+            );
+            let mut codec = PacketCodec::new();
+            let mut buf = BytesMut::new();
+            for (i, &byte) in bytes.iter().enumerate() {
+                buf.put_u8(byte);
+                let decoded = decode(&mut codec, &mut buf);
+                if i + 1 < bytes.len() {
+                    assert_eq!(decoded, None);
+                } else {
+                    assert_eq!(decoded, Some(packet.clone()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_oversized_frames() {
+        let packet = header(1, 0, print(&"x".repeat(64)));
+        for encoding in [Encoding::Bincode, Encoding::Json] {
+            let mut buf = encode(
+                &mut PacketCodec::new().encoding(encoding),
+                std::slice::from_ref(&packet),
+            );
+            let mut small = PacketCodec::with_max_frame_length(16).encoding(encoding);
+            assert!(small.decode(&mut buf).is_err());
+            assert!(small.encode(packet.clone(), &mut BytesMut::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn keeps_the_ids_of_a_malformed_frame() {
+        let mut buf = unknown_packet(5, 2);
+        buf.extend_from_slice(&encode(
+            &mut PacketCodec::new(),
+            &[header(6, 0, print("ok"))],
+        ));
+        let mut codec = PacketCodec::new();
+
+        let error = codec.decode(&mut buf).unwrap().unwrap().unwrap_err();
+        let malformed = MalformedPacket::of(&error).unwrap();
+        assert_eq!((malformed.id, malformed.target_id), (Some(5), Some(2)));
+        // The frame is consumed and the next one still decodes
+        assert_eq!(
+            decode(&mut codec, &mut buf),
+            Some(header(6, 0, print("ok")))
+        );
+    }
+
+    #[test]
+    fn keeps_the_ids_of_a_malformed_json_line() {
+        let mut buf = BytesMut::from("{\"id\":5,\"target_id\":2,\"packet\":\"Unknown\"}\n{}\n");
+        let mut codec = PacketCodec::new();
+
+        let error = codec.decode(&mut buf).unwrap().unwrap().unwrap_err();
+        let malformed = MalformedPacket::of(&error).unwrap();
+        assert_eq!((malformed.id, malformed.target_id), (Some(5), Some(2)));
+        let error = codec.decode(&mut buf).unwrap().unwrap().unwrap_err();
+        let malformed = MalformedPacket::of(&error).unwrap();
+        assert_eq!((malformed.id, malformed.target_id), (None, None));
+    }
+
+    #[tokio::test]
+    async fn sends_packets_through_framed() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = PacketCodec::new().framed(client);
+        let mut server = PacketCodec::new().framed(server);
+        // Larger than the duplex buffer, so it arrives in several reads
+        let packets = [
+            header(1, 0, print(&"x".repeat(200))),
+            header(2, 1, Packet::Confirmation),
+        ];
+        let send = async {
+            for packet in &packets {
+                client.send(packet.clone()).await.unwrap();
+            }
+        };
+        let receive = async {
+            let mut received = Vec::new();
+            for _ in &packets {
+                received.push(server.next().await.unwrap().unwrap().unwrap());
+            }
+            received
+        };
+        let ((), received) = tokio::join!(send, receive);
+        assert_eq!(received, packets);
+    }
+}
@@ -0,0 +1,60 @@
+use std::io;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Smallest encoded packet that is compressed once compression has been negotiated.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// Per-frame compression algorithms that can be negotiated in the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum Compression {
+    Zstd,
+}
+
+impl Compression {
+    /// Algorithms this build can compress and decompress, in order of preference.
+    pub fn supported() -> Vec<Compression> {
+        if cfg!(feature = "compression") {
+            vec![Compression::Zstd]
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "compression")]
+            Compression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+            #[cfg(not(feature = "compression"))]
+            Compression::Zstd => {
+                let _ = data;
+                Err(unsupported(self))
+            }
+        }
+    }
+
+    /// Decompresses `data`, failing if the result would be larger than `max_length`.
+    pub fn decompress(self, data: &[u8], max_length: usize) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "compression")]
+            Compression::Zstd => zstd::bulk::decompress(data, max_length),
+            #[cfg(not(feature = "compression"))]
+            Compression::Zstd => {
+                let _ = (data, max_length);
+                Err(unsupported(self))
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn unsupported(compression: Compression) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{:?} compression requires the `compression` feature",
+            compression
+        ),
+    )
+}
@@ -0,0 +1,70 @@
+use std::{error::Error, fmt, time::Duration};
+
+use super::{ErrorKind, ErrorPacket};
+
+/// An error reported by the agent in response to a packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteError(pub ErrorPacket);
+
+impl RemoteError {
+    pub fn kind(&self) -> ErrorKind {
+        self.0.kind
+    }
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "remote error ({:?}): {}", self.0.kind, self.0.message)?;
+        if let Some(exception_class) = &self.0.exception_class {
+            write!(f, "\ncaused by {}", exception_class)?;
+        }
+        for line in &self.0.stack_trace {
+            write!(f, "\n    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for RemoteError {}
+
+/// The reason a request did not produce a successful response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// The agent answered with an error packet.
+    Remote(RemoteError),
+    /// No response arrived within the given time.
+    Timeout(Duration),
+    /// The connection closed before a response arrived.
+    Disconnected,
+    /// The response arrived but couldn't be decoded, usually because the two sides speak
+    /// different versions.
+    MalformedResponse(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Remote(error) => error.fmt(f),
+            RequestError::Timeout(timeout) => {
+                write!(f, "no response received within {:?}", timeout)
+            }
+            RequestError::Disconnected => write!(f, "connection to the agent closed"),
+            RequestError::MalformedResponse(error) => write!(f, "invalid response: {}", error),
+        }
+    }
+}
+
+impl Error for RequestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RequestError::Remote(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<RemoteError> for RequestError {
+    fn from(error: RemoteError) -> Self {
+        RequestError::Remote(error)
+    }
+}
@@ -0,0 +1,86 @@
+use std::io;
+
+use futures::{SinkExt, StreamExt};
+
+use super::{
+    ErrorKind, ErrorPacket, HelloPacket, Packet, PacketHeader, RemoteError, WelcomePacket,
+    codec::{Encoding, PacketFramed},
+    compression::Compression,
+};
+
+/// Version of the wire protocol; both sides of a connection must agree on it.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Sends the client's [`HelloPacket`] and applies the settings the agent answers with.
+pub async fn connect(framed: &mut PacketFramed) -> io::Result<WelcomePacket> {
+    let hello = Packet::Hello(HelloPacket {
+        protocol_version: PROTOCOL_VERSION,
+        compression: Compression::supported(),
+    });
+    framed.send(hello.as_header()).await?;
+
+    let welcome = match read_packet(framed).await?.packet {
+        Packet::Welcome(welcome) => welcome,
+        Packet::Err(error) => return Err(io::Error::other(RemoteError(error))),
+        packet => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected welcome packet, got {:?}", packet),
+            ));
+        }
+    };
+    framed.codec_mut().set_compression(welcome.compression);
+    Ok(welcome)
+}
+
+/// Waits for a client's [`HelloPacket`] and answers it with the settings for the connection and
+/// the game's runtime `namespace`.
+pub async fn accept(framed: &mut PacketFramed, namespace: &str) -> io::Result<HelloPacket> {
+    let header = read_packet(framed).await?;
+    let hello = match header.packet {
+        Packet::Hello(hello) if hello.protocol_version == PROTOCOL_VERSION => hello,
+        packet => {
+            let message = match packet {
+                Packet::Hello(hello) => format!(
+                    "unsupported protocol version {}, expected {}",
+                    hello.protocol_version, PROTOCOL_VERSION
+                ),
+                packet => format!("expected hello packet, got {:?}", packet),
+            };
+            framed
+                .send(
+                    ErrorPacket::new(ErrorKind::Handshake, message.clone()).as_response(header.id),
+                )
+                .await?;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+    };
+
+    // JSON connections are meant to be read by people, so they are never compressed
+    let encoding = framed
+        .codec()
+        .current_encoding()
+        .unwrap_or(Encoding::Bincode);
+    let supported = match encoding {
+        Encoding::Bincode => Compression::supported(),
+        Encoding::Json => Vec::new(),
+    };
+    let compression = hello
+        .compression
+        .iter()
+        .copied()
+        .find(|compression| supported.contains(compression));
+    let welcome = Packet::Welcome(WelcomePacket {
+        protocol_version: PROTOCOL_VERSION,
+        encoding,
+        compression,
+        namespace: namespace.to_string(),
+    });
+    framed.send(welcome.as_response(header.id)).await?;
+    framed.codec_mut().set_compression(compression);
+    Ok(hello)
+}
+
+async fn read_packet(framed: &mut PacketFramed) -> io::Result<PacketHeader> {
+    framed.next().await.ok_or(io::ErrorKind::UnexpectedEof)??
+}
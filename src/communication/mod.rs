@@ -1,70 +1,380 @@
 pub mod client;
+pub mod codec;
+pub mod compression;
+pub mod error;
+pub mod handshake;
+pub mod recording;
 pub mod server;
+pub mod transfer;
+pub mod value;
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use bincode::{Decode, Encode};
-use futures::{SinkExt, StreamExt};
+use bincode::{
+    Decode, Encode,
+    error::{DecodeError, EncodeError},
+};
+use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
-use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+
+use client::WaitingPackets;
+use codec::Encoding;
+use compression::Compression;
+
+use crate::panic::Panic;
+pub use error::{RemoteError, RequestError};
+pub use value::{JavaValue, ObjectHandle};
 
 pub const ADDRESS: &str = "127.0.0.1:8080";
 
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+/// How long a request waits for its response unless configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
 pub struct PacketHeader {
+    /// Id of this packet, assigned by the sender of a request.
+    id: u64,
+    /// Id of the request this packet responds to, or 0 if it is not a response.
     target_id: u64,
     packet: Packet,
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
 pub enum Packet {
+    Hello(HelloPacket),
+    Welcome(WelcomePacket),
     Print(PrintPacket),
     Toast(ToastPacket),
     Invoke(InvokePacket),
+    GetField(GetFieldPacket),
+    SetField(Box<SetFieldPacket>),
+    /// Lets the agent drop its reference to an object; answered with a confirmation.
+    Release(ObjectHandle),
+    /// Loads a plugin, or reloads it if it is loaded, and calls one of its entry points,
+    /// answered with a [`Packet::Return`].
     Run(RunPacket),
+    /// Loads a plugin and keeps it loaded, answered with a [`Packet::Plugin`].
+    Load(LoadPacket),
+    /// Calls an entry point of a loaded plugin, answered with a [`Packet::Return`].
+    Call(CallPacket),
+    /// Unloads a plugin by name, after calling its `on_unload` hook.
+    Unload(String),
+    /// Unloads a plugin and loads its library again, answered with a [`Packet::Plugin`].
+    Reload(String),
+    /// Lists the loaded plugins, answered with a [`Packet::PluginList`].
+    Plugins,
+    Plugin(PluginInfo),
+    PluginList(Vec<PluginInfo>),
+    /// What a plugin's entry point returned.
+    Return(ReturnPacket),
+    Upload(UploadPacket),
+    Download(DownloadPacket),
+    FileChunk(FileChunkPacket),
+    File(FileInfoPacket),
+    Lock,
+    Unlock,
+    Subscribe(EventKind),
+    Unsubscribe(EventKind),
+    Sessions,
+    SessionInfo(SessionInfoPacket),
+    Event(Event),
+    Custom(CustomPacket),
+    Schedule(SchedulePacket),
+    /// The result of an [`InvokePacket`].
+    Value(JavaValue),
     Confirmation,
-    Err,
+    Err(ErrorPacket),
 }
 
 pub struct PacketSendResult {
     pub id: u64,
-    result: oneshot::Receiver<Packet>,
+    result: oneshot::Receiver<Result<Packet, RequestError>>,
+    timeout: Option<Duration>,
+    waiting_packets: WaitingPackets,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct ErrorPacket {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Name of the Java exception class in the source namespace, if the error was caused by one.
+    pub exception_class: Option<String>,
+    /// Stack trace of the Java exception, remapped to the source namespace, or backtrace of the
+    /// panic.
+    pub stack_trace: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum ErrorKind {
+    UnsupportedPacket,
+    JavaException,
+    Jni,
+    Plugin,
+    Io,
+    ChecksumMismatch,
+    /// Another session holds the agent's exclusive lock.
+    Locked,
+    Handshake,
+    /// No plugin has registered a handler for a custom packet's channel.
+    UnknownChannel,
+    /// A value can't be passed to Java, such as an object that only exists in the game.
+    InvalidArgument,
+    /// The agent panicked while handling the packet, and carried on with the next one.
+    Panic,
+    /// The packet couldn't be decoded, usually because the two sides speak different versions.
+    MalformedPacket,
+    /// A game thread didn't get to the packet in time; it may still handle it later.
+    Timeout,
+}
+
+/// First packet a client sends, describing what it supports.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct HelloPacket {
+    pub protocol_version: u32,
+    pub compression: Vec<Compression>,
+}
+
+/// The agent's answer to [`HelloPacket`], fixing the settings used for the rest of the connection.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct WelcomePacket {
+    pub protocol_version: u32,
+    pub encoding: Encoding,
+    pub compression: Option<Compression>,
+    /// The namespace of the names the game runs with, which the agent maps names to, such as
+    /// [`INTERMEDIARY`](crate::mappings::INTERMEDIARY).
+    pub namespace: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub struct PrintPacket {
     pub message: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub struct ToastPacket {
     pub title: String,
     pub body: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+/// Calls a method by its name in the source namespace, answered with a [`Packet::Value`].
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
 pub struct InvokePacket {
+    /// Name in the source namespace of the class declaring or inheriting the method, such as
+    /// `net/minecraft/client/MinecraftClient`.
     pub class_name: String,
     pub method_name: String,
+    /// Method descriptor using class names in the source namespace, such as
+    /// `()Lnet/minecraft/client/MinecraftClient;`.
     pub desc: String,
+    pub target: InvokeTarget,
+    pub args: Vec<JavaValue>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+/// The instance a method is called on.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub enum InvokeTarget {
+    Static,
+    /// The object returned by another call, made first.
+    Invoke(Box<InvokePacket>),
+    /// An object returned by an earlier request.
+    Object(ObjectHandle),
+}
+
+/// Reads a field by its name in the source namespace, answered with a [`Packet::Value`].
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct GetFieldPacket {
+    /// Name in the source namespace of the class declaring or inheriting the field.
+    pub class_name: String,
+    pub field_name: String,
+    /// Field descriptor using class names in the source namespace.
+    pub desc: String,
+    pub target: InvokeTarget,
+}
+
+/// Writes a field by its name in the source namespace, answered with a confirmation.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct SetFieldPacket {
+    /// Name in the source namespace of the class declaring or inheriting the field.
+    pub class_name: String,
+    pub field_name: String,
+    /// Field descriptor using class names in the source namespace.
+    pub desc: String,
+    pub target: InvokeTarget,
+    pub value: JavaValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub struct RunPacket {
     pub lib: PathBuf,
     pub func: String,
+    /// Passed to the entry point; its format is up to the plugin.
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct LoadPacket {
+    /// Path of the library on the agent's side; the plugin is named after its file name.
+    pub lib: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct CallPacket {
+    pub plugin: String,
+    /// Name of the function the plugin exports.
+    pub entry: String,
+    /// Passed to the entry point; its format is up to the plugin.
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct ReturnPacket {
+    pub payload: Vec<u8>,
+}
+
+/// A plugin loaded into the game.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub lib: PathBuf,
+    /// Custom packet channels the plugin handles.
+    pub channels: Vec<String>,
+}
+
+/// A chunk of a file being uploaded into the agent's file directory.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct UploadPacket {
+    /// Destination, relative to the agent's file directory.
+    pub path: PathBuf,
+    pub offset: u64,
+    pub data: Vec<u8>,
+    /// CRC32 of the whole file, set on the last chunk.
+    pub checksum: Option<u32>,
+}
+
+/// Requests a chunk of a file from the game directory.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct DownloadPacket {
+    /// Source, relative to the game directory.
+    pub path: PathBuf,
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct FileChunkPacket {
+    pub data: Vec<u8>,
+    /// Size of the file when the download started.
+    pub size: u64,
+    /// CRC32 of the first `size` bytes, set on the chunk at offset 0.
+    pub checksum: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct FileInfoPacket {
+    /// Absolute path of the file on the agent's side.
+    pub path: PathBuf,
+    pub size: u64,
+    pub checksum: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct SessionInfoPacket {
+    /// Id of the session that sent the request.
+    pub session: u64,
+    pub sessions: Vec<u64>,
+    /// Session holding the exclusive lock, if any.
+    pub locked_by: Option<u64>,
+}
+
+/// A packet for a channel registered by a plugin; the payload's format is up to the plugin.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct CustomPacket {
+    pub channel: String,
+    pub payload: Vec<u8>,
+}
+
+/// One of the game's threads, which unlike the agent's own threads may touch game state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum GameThread {
+    /// The client's render thread.
+    Client,
+    /// The integrated server's thread, only available while a singleplayer world is open.
+    Server,
+}
+
+/// Has the agent handle `packet` on a game thread, answering with the inner packet's response.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct SchedulePacket {
+    pub thread: GameThread,
+    pub packet: Box<Packet>,
+}
+
+/// Notifications the agent sends to sessions subscribed to their [`EventKind`].
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum Event {
+    SessionOpened { session: u64 },
+    SessionClosed { session: u64 },
+    Locked { session: u64 },
+    Unlocked { session: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub enum EventKind {
+    Sessions,
+    Lock,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::SessionOpened { .. } | Event::SessionClosed { .. } => EventKind::Sessions,
+            Event::Locked { .. } | Event::Unlocked { .. } => EventKind::Lock,
+        }
+    }
 }
 
 impl PacketSendResult {
-    pub async fn get_result(self) -> Packet {
-        self.result.await.unwrap()
+    /// Overrides the connection's default timeout for this request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Waits for the response to this request without a time limit.
+    pub fn no_timeout(mut self) -> Self {
+        self.timeout = None;
+        self
+    }
+
+    pub async fn get_result(mut self) -> Result<Packet, RequestError> {
+        let packet = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut self.result)
+                .await
+                .map_err(|_| RequestError::Timeout(timeout))?,
+            None => (&mut self.result).await,
+        }
+        .map_err(|_| RequestError::Disconnected)??;
+
+        match packet {
+            Packet::Err(error) => Err(RequestError::Remote(RemoteError(error))),
+            packet => Ok(packet),
+        }
+    }
+}
+
+impl Drop for PacketSendResult {
+    fn drop(&mut self) {
+        if let Some(waiting) = self.waiting_packets.lock().unwrap().as_mut() {
+            waiting.remove(&self.id);
+        }
     }
 }
 
 impl Packet {
     pub fn as_header(self) -> PacketHeader {
         PacketHeader {
+            id: 0,
             target_id: 0,
             packet: self,
         }
@@ -72,6 +382,7 @@ impl Packet {
 
     pub fn as_response(self, target_id: u64) -> PacketHeader {
         PacketHeader {
+            id: 0,
             target_id,
             packet: self,
         }
@@ -79,49 +390,112 @@ impl Packet {
 }
 
 impl PacketHeader {
-    pub async fn write<T>(&self, output: &mut T) -> std::io::Result<()>
-    where
-        T: tokio::io::AsyncWriteExt + Unpin,
-    {
-        let mut writer = FramedWrite::new(output, LengthDelimitedCodec::new());
+    pub fn id(&self) -> u64 {
+        self.id
+    }
 
-        let res = bincode::encode_to_vec(self, bincode::config::standard()).unwrap();
-        writer.send(res.into()).await
+    pub fn target_id(&self) -> u64 {
+        self.target_id
     }
 
-    pub async fn read<T>(input: &mut T) -> std::io::Result<Self>
-    where
-        Self: Sized,
-        T: tokio::io::AsyncReadExt + Unpin,
-    {
-        let mut reader = FramedRead::new(input, LengthDelimitedCodec::new());
-        let bytes = reader
-            .next()
-            .await
-            .ok_or(std::io::ErrorKind::UnexpectedEof)??;
+    pub fn packet(&self) -> &Packet {
+        &self.packet
+    }
+
+    pub fn into_packet(self) -> Packet {
+        self.packet
+    }
+}
+
+impl ErrorPacket {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(kind: ErrorKind, message: String) -> Packet {
+        Packet::Err(Self {
+            kind,
+            message,
+            exception_class: None,
+            stack_trace: Vec::new(),
+        })
+    }
+
+    pub fn panic(panic: Panic) -> Packet {
+        Packet::Err(Self {
+            kind: ErrorKind::Panic,
+            message: panic.to_string(),
+            exception_class: None,
+            stack_trace: panic.backtrace,
+        })
+    }
+}
+
+impl CustomPacket {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(channel: String, payload: Vec<u8>) -> Packet {
+        Packet::Custom(Self { channel, payload })
+    }
 
-        Ok(
-            bincode::decode_from_slice(&bytes, bincode::config::standard())
-                .unwrap()
-                .0,
-        )
+    pub fn encode<T: Encode>(channel: String, value: &T) -> Result<Packet, EncodeError> {
+        let payload = bincode::encode_to_vec(value, bincode::config::standard())?;
+        Ok(Self::new(channel, payload))
+    }
+
+    pub fn decode<T: Decode<()>>(&self) -> Result<T, DecodeError> {
+        Ok(bincode::decode_from_slice(&self.payload, bincode::config::standard())?.0)
+    }
+}
+
+impl ReturnPacket {
+    pub fn decode<T: Decode<()>>(&self) -> Result<T, DecodeError> {
+        Ok(bincode::decode_from_slice(&self.payload, bincode::config::standard())?.0)
     }
 }
 
 impl PrintPacket {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(message: String) -> Packet {
         Packet::Print(PrintPacket { message })
     }
 }
 
 impl ToastPacket {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(title: String, body: String) -> Packet {
         Packet::Toast(ToastPacket { title, body })
     }
 }
 
+impl SchedulePacket {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(thread: GameThread, packet: Packet) -> Packet {
+        Packet::Schedule(Self {
+            thread,
+            packet: Box::new(packet),
+        })
+    }
+}
+
+impl InvokePacket {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        class_name: String,
+        method_name: String,
+        desc: String,
+        target: InvokeTarget,
+        args: Vec<JavaValue>,
+    ) -> Packet {
+        Packet::Invoke(Self {
+            class_name,
+            method_name,
+            desc,
+            target,
+            args,
+        })
+    }
+}
+
 impl RunPacket {
-    pub fn new(lib: PathBuf, func: String) -> Packet {
-        Packet::Run(Self { lib, func })
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(lib: PathBuf, func: String, payload: Vec<u8>) -> Packet {
+        Packet::Run(Self { lib, func, payload })
     }
 }
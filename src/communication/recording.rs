@@ -0,0 +1,265 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bincode::{Decode, Encode};
+use tokio::task::JoinHandle;
+
+use super::{
+    ErrorKind, ErrorPacket, Packet, PacketHeader, codec::Transport, server::ServerPacketManager,
+};
+use crate::mappings::INTERMEDIARY;
+
+/// Written at the start of every recording file.
+const MAGIC: &[u8; 8] = b"MCCREC01";
+
+/// Direction of a recorded packet, from the point of view of the side that recorded it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct RecordedPacket {
+    /// Time since the Unix epoch the packet was sent or received at, in microseconds.
+    pub timestamp_micros: u64,
+    pub direction: Direction,
+    pub header: PacketHeader,
+}
+
+/// Appends every packet a connection sends or receives to a recording file.
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        file.write_all(MAGIC)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, direction: Direction, header: &PacketHeader) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO);
+        let entry = RecordedPacket {
+            timestamp_micros: timestamp.as_micros() as u64,
+            direction,
+            header: header.clone(),
+        };
+        let bytes = bincode::encode_to_vec(&entry, bincode::config::standard())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // Written in one go so a crash never leaves half an entry behind
+        self.file.lock().unwrap().write_all(&bytes)
+    }
+}
+
+/// The recorder a packet manager reports to, if any; shared with its listener task.
+#[derive(Clone, Default)]
+pub(crate) struct RecorderSlot(Arc<Mutex<Option<Arc<Recorder>>>>);
+
+impl RecorderSlot {
+    pub(crate) fn set(&self, recorder: Option<Arc<Recorder>>) {
+        *self.0.lock().unwrap() = recorder;
+    }
+
+    pub(crate) fn record(&self, direction: Direction, header: &PacketHeader) {
+        let recorder = self.0.lock().unwrap().clone();
+        if let Some(recorder) = recorder {
+            recorder.record(direction, header).unwrap_or_else(|e| {
+                eprintln!("Failed to record packet: {}", e);
+            });
+        }
+    }
+}
+
+pub fn read_recording(path: impl AsRef<Path>) -> io::Result<Vec<RecordedPacket>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a packet recording",
+        ));
+    }
+
+    let mut entries = Vec::new();
+    while !reader.fill_buf()?.is_empty() {
+        let entry = bincode::decode_from_std_read(&mut reader, bincode::config::standard())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Answers requests with the responses stored in a recording, standing in for a live agent.
+///
+/// Requests are matched to the first unused recorded request with an identical packet, so
+/// a recording made on either the client or the agent side can be replayed. Events are not
+/// replayed.
+pub struct Replay {
+    exchanges: Vec<(Packet, Packet)>,
+}
+
+impl Replay {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(read_recording(path)?))
+    }
+
+    pub fn new(entries: Vec<RecordedPacket>) -> Self {
+        let (requests, responses): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .map(|entry| entry.header)
+            .filter(|header| header.id != 0 || header.target_id != 0)
+            .partition(|header| header.target_id == 0);
+        let exchanges = requests
+            .into_iter()
+            .filter_map(|request| {
+                let response = responses
+                    .iter()
+                    .find(|response| response.target_id == request.id)?;
+                Some((request.packet, response.packet.clone()))
+            })
+            .collect();
+        Self { exchanges }
+    }
+
+    /// Serves the recorded responses over `stream` until the client disconnects.
+    ///
+    /// Recordings don't include the handshake, so the game is said to run with intermediary
+    /// names like one installed with Fabric.
+    pub async fn serve<T: Transport>(self, stream: T) -> io::Result<JoinHandle<()>> {
+        let packet_manager = ServerPacketManager::new(stream, INTERMEDIARY).await?;
+        let exchanges = Mutex::new(self.exchanges);
+        Ok(packet_manager.start_listening(move |packet| {
+            let mut exchanges = exchanges.lock().unwrap();
+            match exchanges.iter().position(|(request, _)| *request == packet) {
+                Some(index) => exchanges.remove(index).1,
+                None => ErrorPacket::new(
+                    ErrorKind::UnsupportedPacket,
+                    format!("no recorded response for {:?}", packet),
+                ),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        communication::{PrintPacket, RequestError},
+        initialization::MinecraftProcess,
+        mock::MockAgent,
+    };
+
+    fn recording_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mc-connect-{}-{}.rec", name, std::process::id()))
+    }
+
+    async fn replay(replay: Replay) -> MinecraftProcess {
+        let (client, server) = tokio::io::duplex(1024 * 1024);
+        let (serving, process) = tokio::join!(
+            replay.serve(server),
+            MinecraftProcess::connect(client, "1.21".to_string())
+        );
+        serving.unwrap();
+        process.unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_what_was_recorded() {
+        let path = recording_path("round-trip");
+        let agent = MockAgent::new();
+        agent.respond(
+            |packet| matches!(packet, Packet::Plugins),
+            Packet::PluginList(Vec::new()),
+        );
+        let process = agent.process("1.21").await.unwrap();
+        let recorder = Arc::new(Recorder::create(&path).unwrap());
+        process.packet_manager.set_recorder(Some(recorder));
+        let plugins = process.plugins().await.unwrap().get_result().await.unwrap();
+        let printed = process
+            .println("hi")
+            .await
+            .unwrap()
+            .get_result()
+            .await
+            .unwrap();
+        process.packet_manager.set_recorder(None);
+
+        let entries = read_recording(&path).unwrap();
+        let directions = entries
+            .iter()
+            .map(|entry| entry.direction)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            directions,
+            [
+                Direction::Sent,
+                Direction::Received,
+                Direction::Sent,
+                Direction::Received
+            ]
+        );
+        assert_eq!(entries[1].header.target_id, entries[0].header.id);
+
+        // Replayed out of order, and with other ids than recorded
+        let process = replay(Replay::open(&path).unwrap()).await;
+        drop(process.lock().await.unwrap());
+        let replayed = process.println("hi").await.unwrap().get_result().await;
+        assert_eq!(replayed.unwrap(), printed);
+        let replayed = process.plugins().await.unwrap().get_result().await;
+        assert_eq!(replayed.unwrap(), plugins);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_requests_that_were_not_recorded() {
+        let print = |id, message: &str| PacketHeader {
+            id,
+            target_id: 0,
+            packet: PrintPacket::new(message.to_string()),
+        };
+        let entries = [print(1, "hi"), Packet::Confirmation.as_response(1)]
+            .into_iter()
+            .map(|header| RecordedPacket {
+                timestamp_micros: 0,
+                direction: Direction::Sent,
+                header,
+            })
+            .collect();
+        let process = replay(Replay::new(entries)).await;
+
+        let unknown = process.print("bye").await.unwrap().get_result().await;
+        let Err(RequestError::Remote(error)) = unknown else {
+            panic!("replayed {:?}", unknown);
+        };
+        assert_eq!(error.kind(), ErrorKind::UnsupportedPacket);
+        let known = process.print("hi").await.unwrap().get_result().await;
+        assert_eq!(known.unwrap(), Packet::Confirmation);
+        // Each recorded response is only replayed once
+        let again = process.print("hi").await.unwrap().get_result().await;
+        assert!(matches!(again, Err(RequestError::Remote(_))));
+    }
+
+    #[test]
+    fn refuses_files_that_are_not_recordings() {
+        let path = recording_path("not-a-recording");
+        std::fs::write(&path, b"MCCREC00").unwrap();
+        let error = read_recording(&path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,65 +1,106 @@
-use std::{
-    io::ErrorKind,
-    sync::{
-        Arc,
-        atomic::{AtomicU64, Ordering},
-    },
-};
+use std::sync::Arc;
 
-use tokio::{
-    net::{TcpStream, tcp},
-    sync::Mutex,
-    task::JoinHandle,
-};
+use futures::{SinkExt, StreamExt};
+use tokio::{sync::Mutex, task::JoinHandle};
 
-use super::{Packet, PacketHeader};
+use super::{
+    ErrorKind, ErrorPacket, HelloPacket, Packet,
+    codec::{MalformedPacket, PacketCodec, PacketSink, PacketStream, Transport},
+    handshake,
+    recording::{Direction, Recorder, RecorderSlot},
+};
 
+#[derive(Clone)]
 pub struct ServerPacketManager {
-    num_packets: Arc<AtomicU64>,
-    pub stream_read: Arc<Mutex<tcp::OwnedReadHalf>>,
-    pub stream_write: Arc<Mutex<tcp::OwnedWriteHalf>>,
+    pub stream_read: Arc<Mutex<PacketStream>>,
+    pub stream_write: Arc<Mutex<PacketSink>>,
+    hello: HelloPacket,
+    recorder: RecorderSlot,
 }
 
 impl ServerPacketManager {
-    pub fn new(stream: TcpStream) -> Self {
-        let (read, write) = stream.into_split();
-        Self {
-            num_packets: Arc::new(AtomicU64::new(0)),
+    /// Waits for the client's handshake over `stream` and answers it, telling it the game runs
+    /// with names of `namespace`.
+    pub async fn new<T: Transport>(stream: T, namespace: &str) -> std::io::Result<Self> {
+        Self::with_codec(stream, PacketCodec::new(), namespace).await
+    }
+
+    pub async fn with_codec<T: Transport>(
+        stream: T,
+        codec: PacketCodec,
+        namespace: &str,
+    ) -> std::io::Result<Self> {
+        let mut framed = codec.framed(stream);
+        let hello = handshake::accept(&mut framed, namespace).await?;
+        let (write, read) = framed.split();
+        Ok(Self {
             stream_read: Arc::new(Mutex::new(read)),
             stream_write: Arc::new(Mutex::new(write)),
-        }
+            hello,
+            recorder: RecorderSlot::default(),
+        })
+    }
+
+    /// What the client announced it supports during the handshake.
+    pub fn hello(&self) -> &HelloPacket {
+        &self.hello
+    }
+
+    /// Records every packet sent or received from now on, or stops recording with `None`.
+    pub fn set_recorder(&self, recorder: Option<Arc<Recorder>>) {
+        self.recorder.set(recorder);
+    }
+
+    /// Sends a packet that isn't a response to any request, such as an event.
+    pub async fn send_packet(&self, packet: Packet) -> std::io::Result<()> {
+        let packet = packet.as_header();
+        self.recorder.record(Direction::Sent, &packet);
+        self.stream_write.lock().await.send(packet).await
     }
 
     pub fn start_listening<F>(&self, packet_handler: F) -> JoinHandle<()>
     where
         F: Fn(Packet) -> Packet + Send + 'static,
     {
+        let recorder = self.recorder.clone();
         let stream_read = Arc::clone(&self.stream_read);
         let stream_write = Arc::clone(&self.stream_write);
-        let num_packets = Arc::clone(&self.num_packets);
 
         tokio::spawn(async move {
-            loop {
-                let mut guard = stream_read.lock().await;
-                match PacketHeader::read(&mut *guard).await {
-                    Ok(packet) => {
-                        let res = packet_handler(packet.packet);
+            let mut guard = stream_read.lock().await;
+            while let Some(frame) = guard.next().await {
+                match frame {
+                    Ok(Ok(packet)) => {
+                        recorder.record(Direction::Received, &packet);
+                        let res = packet_handler(packet.packet).as_response(packet.id);
+                        recorder.record(Direction::Sent, &res);
                         let mut write_guard = stream_write.lock().await;
-                        PacketHeader {
-                            target_id: num_packets.fetch_add(1, Ordering::AcqRel) + 1,
-                            packet: res,
+                        if let Err(e) = write_guard.send(res).await {
+                            eprintln!("Error sending packet: {}", e);
+                            break;
                         }
-                        .write(&mut *write_guard)
-                        .await
-                        .unwrap();
                     }
-                    Err(e) => {
-                        if e.kind() == ErrorKind::UnexpectedEof {
+                    Ok(Err(e)) => {
+                        eprintln!("Dropping malformed packet: {}", e);
+                        // A request left unanswered would wait for its whole timeout, so
+                        // without an id to answer, the client is disconnected instead
+                        let Some(id) = MalformedPacket::of(&e).and_then(|malformed| malformed.id)
+                        else {
+                            break;
+                        };
+                        let res = ErrorPacket::new(ErrorKind::MalformedPacket, e.to_string())
+                            .as_response(id);
+                        recorder.record(Direction::Sent, &res);
+                        let mut write_guard = stream_write.lock().await;
+                        if let Err(e) = write_guard.send(res).await {
+                            eprintln!("Error sending packet: {}", e);
                             break;
-                        } else {
-                            eprintln!("Error receiving packet: {}", e);
                         }
                     }
+                    Err(e) => {
+                        eprintln!("Error receiving packet: {}", e);
+                        break;
+                    }
                 }
             }
         })
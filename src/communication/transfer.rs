@@ -0,0 +1,95 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use super::{Packet, RequestError};
+
+/// Largest amount of file data sent in a single packet.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Directory, relative to the game directory, that uploaded files are stored in.
+pub const AGENT_FILES_DIR: &str = "mc-connect/files";
+
+/// Checks that a transfer path stays inside the directory it is resolved against.
+pub fn validate_relative_path(path: &Path) -> io::Result<&Path> {
+    let is_contained = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if is_contained && path.components().next().is_some() {
+        Ok(path)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("transfer path must be relative: {}", path.display()),
+        ))
+    }
+}
+
+/// Computes the CRC32 of the first `size` bytes read from `reader`.
+pub fn checksum(reader: impl Read, size: u64) -> io::Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut reader = reader.take(size);
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buf[..read]);
+    }
+}
+
+pub fn checksum_file(path: &Path) -> io::Result<(u64, u32)> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    Ok((size, checksum(file, size)?))
+}
+
+#[derive(Debug)]
+pub enum TransferError {
+    Io(io::Error),
+    Request(RequestError),
+    ChecksumMismatch { path: PathBuf },
+    UnexpectedResponse(Packet),
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferError::Io(e) => write!(f, "file transfer failed: {}", e),
+            TransferError::Request(e) => write!(f, "file transfer failed: {}", e),
+            TransferError::ChecksumMismatch { path } => {
+                write!(f, "checksum mismatch transferring {}", path.display())
+            }
+            TransferError::UnexpectedResponse(packet) => {
+                write!(f, "unexpected response to file transfer: {:?}", packet)
+            }
+        }
+    }
+}
+
+impl Error for TransferError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransferError::Io(e) => Some(e),
+            TransferError::Request(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TransferError {
+    fn from(error: io::Error) -> Self {
+        TransferError::Io(error)
+    }
+}
+
+impl From<RequestError> for TransferError {
+    fn from(error: RequestError) -> Self {
+        TransferError::Request(error)
+    }
+}
@@ -0,0 +1,631 @@
+//! Java values on the wire, and the Java types they are passed as.
+//!
+//! [`JavaType`] parses descriptors, and [`JavaValue::convert`] is the single place deciding
+//! which values a parameter of a given type accepts.
+
+use std::fmt;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+pub const STRING_CLASS: &str = "java/lang/String";
+/// Names of Minecraft's chat component interface in Yarn's, intermediary and Mojang's
+/// namespaces, any of which may be the source namespace.
+pub const TEXT_CLASSES: &[&str] = &[
+    "net/minecraft/text/Text",
+    "net/minecraft/class_2561",
+    "net/minecraft/network/chat/Component",
+];
+
+/// A Java value passed to or returned from the game.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub enum JavaValue {
+    Null,
+    Boolean(bool),
+    Byte(i8),
+    Char(u16),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    /// The elements of an array; their type is taken from the descriptor it's passed as.
+    Array(Vec<JavaValue>),
+    /// An enum constant, by the names of its class and of the constant in the source namespace.
+    Enum {
+        class_name: String,
+        name: String,
+    },
+    /// A chat component, of one of the [`TEXT_CLASSES`], as its plain string.
+    Text(String),
+    /// An object that only exists in the game, identified by a handle into the session.
+    Object {
+        handle: ObjectHandle,
+        /// Name of the object's runtime class in the source namespace.
+        class_name: String,
+    },
+}
+
+/// Refers to an object the agent keeps alive for a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub struct ObjectHandle(pub u64);
+
+/// A Java type, as written in descriptors with class names in the source namespace.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub enum JavaType {
+    Boolean,
+    Byte,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    /// Only valid as a method's return type.
+    Void,
+    /// A class, such as `java/lang/String`.
+    Object(String),
+    Array(Box<JavaType>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidDescriptor(pub String);
+
+impl fmt::Display for InvalidDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid descriptor: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidDescriptor {}
+
+/// A value that doesn't fit the type it was passed or asked for as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnexpectedValue {
+    pub expected: JavaType,
+    pub found: JavaValue,
+}
+
+impl fmt::Display for UnexpectedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, found {:?}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for UnexpectedValue {}
+
+impl JavaType {
+    /// Parses a field descriptor such as `I` or `[Lnet/minecraft/text/Text;`.
+    pub fn parse(desc: &str) -> Result<JavaType, InvalidDescriptor> {
+        match Self::parse_prefix(desc) {
+            Some((ty, "")) => Ok(ty),
+            _ => Err(InvalidDescriptor(desc.to_string())),
+        }
+    }
+
+    /// Parses a method descriptor into its parameter and return types.
+    pub fn parse_method(desc: &str) -> Result<(Vec<JavaType>, JavaType), InvalidDescriptor> {
+        let invalid = || InvalidDescriptor(desc.to_string());
+        let mut rest = desc.strip_prefix('(').ok_or_else(invalid)?;
+        let mut params = Vec::new();
+        while let Some((param, after)) = Self::parse_prefix(rest) {
+            params.push(param);
+            rest = after;
+        }
+        let rest = rest.strip_prefix(')').ok_or_else(invalid)?;
+        let ret = Self::parse(rest).map_err(|_| invalid())?;
+        if params.contains(&JavaType::Void) {
+            return Err(invalid());
+        }
+        Ok((params, ret))
+    }
+
+    fn parse_prefix(desc: &str) -> Option<(JavaType, &str)> {
+        let mut chars = desc.chars();
+        let ty = match chars.next()? {
+            'Z' => JavaType::Boolean,
+            'B' => JavaType::Byte,
+            'C' => JavaType::Char,
+            'S' => JavaType::Short,
+            'I' => JavaType::Int,
+            'J' => JavaType::Long,
+            'F' => JavaType::Float,
+            'D' => JavaType::Double,
+            'V' => JavaType::Void,
+            'L' => {
+                let (class_name, rest) = chars.as_str().split_once(';')?;
+                if class_name.is_empty() {
+                    return None;
+                }
+                return Some((JavaType::Object(class_name.to_string()), rest));
+            }
+            '[' => {
+                let (element, rest) = Self::parse_prefix(chars.as_str())?;
+                if element == JavaType::Void {
+                    return None;
+                }
+                return Some((JavaType::Array(Box::new(element)), rest));
+            }
+            _ => return None,
+        };
+        Some((ty, chars.as_str()))
+    }
+
+    pub fn descriptor(&self) -> String {
+        match self {
+            JavaType::Boolean => "Z".to_string(),
+            JavaType::Byte => "B".to_string(),
+            JavaType::Char => "C".to_string(),
+            JavaType::Short => "S".to_string(),
+            JavaType::Int => "I".to_string(),
+            JavaType::Long => "J".to_string(),
+            JavaType::Float => "F".to_string(),
+            JavaType::Double => "D".to_string(),
+            JavaType::Void => "V".to_string(),
+            JavaType::Object(class_name) => format!("L{};", class_name),
+            JavaType::Array(element) => format!("[{}", element.descriptor()),
+        }
+    }
+
+    pub fn is_primitive(&self) -> bool {
+        !matches!(self, JavaType::Object(_) | JavaType::Array(_))
+    }
+
+    /// The class a primitive type is boxed as.
+    pub fn box_class(&self) -> Option<&'static str> {
+        match self {
+            JavaType::Boolean => Some("java/lang/Boolean"),
+            JavaType::Byte => Some("java/lang/Byte"),
+            JavaType::Char => Some("java/lang/Character"),
+            JavaType::Short => Some("java/lang/Short"),
+            JavaType::Int => Some("java/lang/Integer"),
+            JavaType::Long => Some("java/lang/Long"),
+            JavaType::Float => Some("java/lang/Float"),
+            JavaType::Double => Some("java/lang/Double"),
+            JavaType::Void | JavaType::Object(_) | JavaType::Array(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for JavaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JavaType::Boolean => write!(f, "boolean"),
+            JavaType::Byte => write!(f, "byte"),
+            JavaType::Char => write!(f, "char"),
+            JavaType::Short => write!(f, "short"),
+            JavaType::Int => write!(f, "int"),
+            JavaType::Long => write!(f, "long"),
+            JavaType::Float => write!(f, "float"),
+            JavaType::Double => write!(f, "double"),
+            JavaType::Void => write!(f, "void"),
+            JavaType::Object(class_name) => write!(f, "{}", class_name),
+            JavaType::Array(element) => write!(f, "{}[]", element),
+        }
+    }
+}
+
+impl JavaValue {
+    /// The type of a primitive value.
+    pub fn primitive_type(&self) -> Option<JavaType> {
+        match self {
+            JavaValue::Boolean(_) => Some(JavaType::Boolean),
+            JavaValue::Byte(_) => Some(JavaType::Byte),
+            JavaValue::Char(_) => Some(JavaType::Char),
+            JavaValue::Short(_) => Some(JavaType::Short),
+            JavaValue::Int(_) => Some(JavaType::Int),
+            JavaValue::Long(_) => Some(JavaType::Long),
+            JavaValue::Float(_) => Some(JavaType::Float),
+            JavaValue::Double(_) => Some(JavaType::Double),
+            _ => None,
+        }
+    }
+
+    /// Converts the value for a parameter or field of type `ty`.
+    ///
+    /// Primitives are widened the way Java widens them, so an `Int` can be passed as a `long`.
+    /// Object types are only checked loosely, as whether a handle's class is assignable to
+    /// `ty` can only be answered by the game.
+    pub fn convert(self, ty: &JavaType) -> Result<JavaValue, UnexpectedValue> {
+        use JavaValue as V;
+
+        let converted = match (ty, self) {
+            (JavaType::Boolean, V::Boolean(value)) => V::Boolean(value),
+            (JavaType::Byte, V::Byte(value)) => V::Byte(value),
+            (JavaType::Char, V::Char(value)) => V::Char(value),
+            (JavaType::Short, V::Short(value)) => V::Short(value),
+            (JavaType::Short, V::Byte(value)) => V::Short(value.into()),
+            (JavaType::Int, V::Int(value)) => V::Int(value),
+            (JavaType::Int, V::Short(value)) => V::Int(value.into()),
+            (JavaType::Int, V::Byte(value)) => V::Int(value.into()),
+            (JavaType::Int, V::Char(value)) => V::Int(value.into()),
+            (JavaType::Long, V::Long(value)) => V::Long(value),
+            (JavaType::Long, V::Int(value)) => V::Long(value.into()),
+            (JavaType::Long, V::Short(value)) => V::Long(value.into()),
+            (JavaType::Long, V::Byte(value)) => V::Long(value.into()),
+            (JavaType::Long, V::Char(value)) => V::Long(value.into()),
+            (JavaType::Float, V::Float(value)) => V::Float(value),
+            (JavaType::Float, V::Long(value)) => V::Float(value as f32),
+            (JavaType::Float, V::Int(value)) => V::Float(value as f32),
+            (JavaType::Float, V::Short(value)) => V::Float(value.into()),
+            (JavaType::Float, V::Byte(value)) => V::Float(value.into()),
+            (JavaType::Float, V::Char(value)) => V::Float(value.into()),
+            (JavaType::Double, V::Double(value)) => V::Double(value),
+            (JavaType::Double, V::Float(value)) => V::Double(value.into()),
+            (JavaType::Double, V::Long(value)) => V::Double(value as f64),
+            (JavaType::Double, V::Int(value)) => V::Double(value.into()),
+            (JavaType::Double, V::Short(value)) => V::Double(value.into()),
+            (JavaType::Double, V::Byte(value)) => V::Double(value.into()),
+            (JavaType::Double, V::Char(value)) => V::Double(value.into()),
+            (JavaType::Object(_) | JavaType::Array(_), V::Null) => V::Null,
+            (JavaType::Array(element), V::Array(values)) => V::Array(
+                values
+                    .into_iter()
+                    .map(|value| value.convert(element))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (JavaType::Object(class_name), V::String(value))
+                if is_supertype_of_string(class_name) =>
+            {
+                V::String(value)
+            }
+            (JavaType::Object(class_name), V::Text(value) | V::String(value))
+                if TEXT_CLASSES.contains(&class_name.as_str()) =>
+            {
+                V::Text(value)
+            }
+            (JavaType::Object(class_name), V::Text(value)) if class_name == "java/lang/Object" => {
+                V::Text(value)
+            }
+            (JavaType::Object(class_name), value)
+                if value
+                    .primitive_type()
+                    .and_then(|primitive| primitive.box_class())
+                    .is_some_and(|box_class| box_class == class_name) =>
+            {
+                value
+            }
+            (JavaType::Object(_), value @ (V::Enum { .. } | V::Object { .. })) => value,
+            (JavaType::Object(class_name), value) if class_name == "java/lang/Object" => {
+                // Primitives are boxed, arrays stay arrays of objects
+                match value {
+                    V::Array(values) => V::Array(
+                        values
+                            .into_iter()
+                            .map(|value| value.convert(ty))
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    value => value,
+                }
+            }
+            (_, found) => {
+                return Err(UnexpectedValue {
+                    expected: ty.clone(),
+                    found,
+                });
+            }
+        };
+        Ok(converted)
+    }
+}
+
+fn is_supertype_of_string(class_name: &str) -> bool {
+    matches!(
+        class_name,
+        STRING_CLASS | "java/lang/Object" | "java/lang/CharSequence" | "java/lang/Comparable"
+    )
+}
+
+macro_rules! impl_conversions {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for JavaValue {
+                fn from(value: $ty) -> Self {
+                    JavaValue::$variant(value)
+                }
+            }
+
+            impl TryFrom<JavaValue> for $ty {
+                type Error = UnexpectedValue;
+
+                fn try_from(value: JavaValue) -> Result<Self, Self::Error> {
+                    match value {
+                        JavaValue::$variant(value) => Ok(value),
+                        found => Err(UnexpectedValue {
+                            expected: JavaType::$variant,
+                            found,
+                        }),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_conversions! {
+    bool => Boolean,
+    i8 => Byte,
+    i16 => Short,
+    i32 => Int,
+    i64 => Long,
+    f32 => Float,
+    f64 => Double,
+}
+
+impl From<String> for JavaValue {
+    fn from(value: String) -> Self {
+        JavaValue::String(value)
+    }
+}
+
+impl From<&str> for JavaValue {
+    fn from(value: &str) -> Self {
+        JavaValue::String(value.to_string())
+    }
+}
+
+impl<T: Into<JavaValue>> From<Option<T>> for JavaValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(JavaValue::Null, Into::into)
+    }
+}
+
+impl<T: Into<JavaValue>> From<Vec<T>> for JavaValue {
+    fn from(values: Vec<T>) -> Self {
+        JavaValue::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl TryFrom<JavaValue> for char {
+    type Error = UnexpectedValue;
+
+    fn try_from(value: JavaValue) -> Result<Self, Self::Error> {
+        match value {
+            // Half of a surrogate pair isn't a `char` on its own
+            JavaValue::Char(value) if char::from_u32(value.into()).is_some() => {
+                Ok(char::from_u32(value.into()).unwrap())
+            }
+            found => Err(UnexpectedValue {
+                expected: JavaType::Char,
+                found,
+            }),
+        }
+    }
+}
+
+/// Accepts both strings and text components, which are often interchangeable to a caller.
+impl TryFrom<JavaValue> for String {
+    type Error = UnexpectedValue;
+
+    fn try_from(value: JavaValue) -> Result<Self, Self::Error> {
+        match value {
+            JavaValue::String(value) | JavaValue::Text(value) => Ok(value),
+            found => Err(UnexpectedValue {
+                expected: JavaType::Object(STRING_CLASS.to_string()),
+                found,
+            }),
+        }
+    }
+}
+
+impl<T: TryFrom<JavaValue, Error = UnexpectedValue>> TryFrom<JavaValue> for Option<T> {
+    type Error = UnexpectedValue;
+
+    fn try_from(value: JavaValue) -> Result<Self, Self::Error> {
+        match value {
+            JavaValue::Null => Ok(None),
+            value => T::try_from(value).map(Some),
+        }
+    }
+}
+
+impl<T: TryFrom<JavaValue, Error = UnexpectedValue>> TryFrom<JavaValue> for Vec<T> {
+    type Error = UnexpectedValue;
+
+    fn try_from(value: JavaValue) -> Result<Self, Self::Error> {
+        match value {
+            JavaValue::Array(values) => values.into_iter().map(T::try_from).collect(),
+            found => Err(UnexpectedValue {
+                expected: JavaType::Array(Box::new(JavaType::Object(
+                    "java/lang/Object".to_string(),
+                ))),
+                found,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(class_name: &str) -> JavaType {
+        JavaType::Object(class_name.to_string())
+    }
+
+    fn array(element: JavaType) -> JavaType {
+        JavaType::Array(Box::new(element))
+    }
+
+    #[test]
+    fn parses_field_descriptors() {
+        assert_eq!(JavaType::parse("I"), Ok(JavaType::Int));
+        assert_eq!(
+            JavaType::parse("Ljava/lang/String;"),
+            Ok(object(STRING_CLASS))
+        );
+        assert_eq!(
+            JavaType::parse("[[Lnet/minecraft/class_1$class_2;"),
+            Ok(array(array(object("net/minecraft/class_1$class_2"))))
+        );
+        for ty in [
+            JavaType::Long,
+            array(JavaType::Boolean),
+            array(array(object("net/minecraft/text/Text"))),
+        ] {
+            assert_eq!(JavaType::parse(&ty.descriptor()), Ok(ty));
+        }
+    }
+
+    #[test]
+    fn parses_method_descriptors() {
+        assert_eq!(
+            JavaType::parse_method("()V"),
+            Ok((Vec::new(), JavaType::Void))
+        );
+        assert_eq!(
+            JavaType::parse_method("(I[JLjava/util/List;[[Ljava/lang/Object;D)[Ljava/lang/String;"),
+            Ok((
+                vec![
+                    JavaType::Int,
+                    array(JavaType::Long),
+                    object("java/util/List"),
+                    array(array(object("java/lang/Object"))),
+                    JavaType::Double,
+                ],
+                array(object(STRING_CLASS)),
+            ))
+        );
+    }
+
+    #[test]
+    fn refuses_malformed_descriptors() {
+        for desc in [
+            "",
+            "V[",
+            "II",
+            "X",
+            "[",
+            "[V",
+            "L;",
+            "Ljava/lang/String",
+            "java/lang/String",
+            "Lé;;",
+        ] {
+            assert_eq!(
+                JavaType::parse(desc),
+                Err(InvalidDescriptor(desc.to_string())),
+                "{}",
+                desc
+            );
+        }
+        for desc in [
+            "",
+            "(",
+            ")V",
+            "()",
+            "(I",
+            "(V)V",
+            "(I)",
+            "(I)II",
+            "(X)V",
+            "(L;)V",
+            "(Ljava/lang)V",
+            "(I)[V",
+            "é(I)V",
+        ] {
+            assert_eq!(
+                JavaType::parse_method(desc),
+                Err(InvalidDescriptor(desc.to_string())),
+                "{}",
+                desc
+            );
+        }
+    }
+
+    #[test]
+    fn widens_primitives() {
+        assert_eq!(
+            JavaValue::Int(7).convert(&JavaType::Long),
+            Ok(JavaValue::Long(7))
+        );
+        assert_eq!(
+            JavaValue::Char(65).convert(&JavaType::Int),
+            Ok(JavaValue::Int(65))
+        );
+        assert_eq!(
+            JavaValue::Byte(-1).convert(&JavaType::Short),
+            Ok(JavaValue::Short(-1))
+        );
+        assert_eq!(
+            JavaValue::Float(0.5).convert(&JavaType::Double),
+            Ok(JavaValue::Double(0.5))
+        );
+        assert_eq!(
+            JavaValue::Long(3).convert(&JavaType::Float),
+            Ok(JavaValue::Float(3.0))
+        );
+    }
+
+    #[test]
+    fn refuses_narrowing_conversions() {
+        for (value, ty) in [
+            (JavaValue::Long(1), JavaType::Int),
+            (JavaValue::Int(1), JavaType::Short),
+            (JavaValue::Int(1), JavaType::Char),
+            (JavaValue::Short(1), JavaType::Byte),
+            (JavaValue::Short(1), JavaType::Char),
+            (JavaValue::Char(1), JavaType::Short),
+            (JavaValue::Double(1.0), JavaType::Float),
+            (JavaValue::Float(1.0), JavaType::Long),
+            (JavaValue::Int(1), JavaType::Boolean),
+            (JavaValue::Boolean(true), JavaType::Int),
+        ] {
+            assert_eq!(
+                value.clone().convert(&ty),
+                Err(UnexpectedValue {
+                    expected: ty,
+                    found: value,
+                })
+            );
+        }
+        // Elements of an array are converted, and narrowed no more than single values
+        let longs = JavaValue::Array(vec![JavaValue::Int(1), JavaValue::Long(2)]);
+        assert!(longs.convert(&array(JavaType::Int)).is_err());
+    }
+
+    #[test]
+    fn converts_objects() {
+        let values = JavaValue::Array(vec![JavaValue::Byte(1), JavaValue::Int(2)]);
+        assert_eq!(
+            values.convert(&array(JavaType::Long)),
+            Ok(JavaValue::Array(vec![
+                JavaValue::Long(1),
+                JavaValue::Long(2)
+            ]))
+        );
+        assert_eq!(
+            JavaValue::Null.convert(&array(JavaType::Int)),
+            Ok(JavaValue::Null)
+        );
+        assert!(JavaValue::Null.convert(&JavaType::Int).is_err());
+        assert_eq!(
+            JavaValue::from("hi").convert(&object("java/lang/CharSequence")),
+            Ok(JavaValue::String("hi".to_string()))
+        );
+        assert_eq!(
+            JavaValue::from("hi").convert(&object("net/minecraft/class_2561")),
+            Ok(JavaValue::Text("hi".to_string()))
+        );
+        assert!(
+            JavaValue::Text("hi".to_string())
+                .convert(&object(STRING_CLASS))
+                .is_err()
+        );
+        assert_eq!(
+            JavaValue::Int(1).convert(&object("java/lang/Integer")),
+            Ok(JavaValue::Int(1))
+        );
+        assert!(
+            JavaValue::Int(1)
+                .convert(&object("java/lang/Long"))
+                .is_err()
+        );
+        assert!(
+            JavaValue::from("hi")
+                .convert(&object("java/util/List"))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Running work on the game's own threads.
+//!
+//! Minecraft isn't thread-safe: packet handlers and plugins run on the agent's threads, so
+//! anything that reads world state or changes the UI has to be handed to the client or
+//! integrated server thread with [`execute`].
+//!
+//! A task travels to Java as a `NativeTask` holding a pointer to the closure and to the
+//! function that runs it. The function is compiled into the library that created the task,
+//! so plugins can schedule closures without sharing any types with the agent.
+
+use std::ffi::c_void;
+
+use jni::{JNIEnv, objects::JValue, sys};
+use tokio::sync::oneshot;
+
+use crate::{communication::GameThread, panic};
+
+const NATIVE_TASK_CLASS: &str = "com/codemob/mcconnect/NativeTask";
+const TOOLS_CLASS: &str = "com/codemob/mcconnect/Tools";
+
+/// Called by a `NativeTask` on the game thread with the data pointer it was created with, or
+/// with a null `env` to free the task once the game has thrown it away without running it.
+pub type TaskFn = unsafe extern "C" fn(data: *mut c_void, env: *mut sys::JNIEnv);
+
+type Task = Box<dyn FnOnce(&mut JNIEnv) + Send>;
+
+/// Queues `task` on `thread`, returning a receiver for its result.
+///
+/// If the calling thread already is `thread` the task runs before this returns, unless the
+/// thread is running one of its queued tasks, in which case the task is queued behind it. So
+/// don't block on the receiver from a game thread: the task it waits for may never run.
+/// If the game drops the task without running it, for example when the integrated server
+/// shuts down, the receiver fails once the task is garbage collected, which may take a while:
+/// wait for it with a timeout.
+pub fn execute<F, R>(
+    env: &mut JNIEnv,
+    thread: GameThread,
+    task: F,
+) -> jni::errors::Result<oneshot::Receiver<R>>
+where
+    F: FnOnce(&mut JNIEnv) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let task: Task = Box::new(move |env| {
+        // The caller may have stopped waiting, which is fine
+        let _ = tx.send(task(env));
+    });
+    let data = Box::into_raw(Box::new(task));
+
+    let queued = env
+        .new_object(
+            NATIVE_TASK_CLASS,
+            "(JJ)V",
+            &[
+                JValue::Long(run_task as TaskFn as usize as i64),
+                JValue::Long(data as usize as i64),
+            ],
+        )
+        .and_then(|native_task| {
+            env.call_static_method(
+                TOOLS_CLASS,
+                "execute",
+                "(ZLjava/lang/Runnable;)V",
+                &[
+                    JValue::Bool((thread == GameThread::Server).into()),
+                    (&native_task).into(),
+                ],
+            )
+        });
+    if let Err(e) = queued {
+        // The task was never queued, so nothing else will free it
+        drop(unsafe { Box::from_raw(data) });
+        return Err(e);
+    }
+    Ok(rx)
+}
+
+unsafe extern "C" fn run_task(data: *mut c_void, env: *mut sys::JNIEnv) {
+    // SAFETY: `data` comes from `Box::into_raw` in `execute`, and `NativeTask` only runs once
+    let task = unsafe { Box::from_raw(data as *mut Task) };
+    if env.is_null() {
+        // Dropping the task's sender tells whoever waits for it that it won't run
+        if let Err(panic) = panic::catch(|| drop(task)) {
+            eprintln!("Dropping task of game thread {}", panic);
+        }
+        return;
+    }
+    match unsafe { JNIEnv::from_raw(env) } {
+        // Dropping the task's sender tells whoever waits for it that it didn't finish
+        Ok(mut env) => {
+            if let Err(panic) = panic::catch(|| task(&mut env)) {
+                eprintln!("Task on game thread {}", panic);
+            }
+        }
+        Err(e) => eprintln!("Failed to run task on game thread: {}", e),
+    }
+}
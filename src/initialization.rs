@@ -1,7 +1,7 @@
 use std::{
     env::{self, current_exe},
     fs::{self, File, set_permissions},
-    io::Write,
+    io::{self, Write},
     os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
     thread::{self, sleep},
@@ -13,7 +13,7 @@ use jni::{InitArgsBuilder, JavaVM};
 use sysinfo::{Process, System};
 use tokio::net::TcpStream;
 
-use crate::communication::{ADDRESS, client::ClientPacketManager};
+use crate::communication::{ADDRESS, client::ClientPacketManager, codec::Transport};
 
 const AGENT_JAR: &[u8] = include_bytes!("../agent/java/build/libs/agent-1.0.jar");
 
@@ -31,14 +31,68 @@ fn write_agent_jar(dir: &Path) -> std::io::Result<String> {
     Ok(path.display().to_string())
 }
 
+/// How to attach the agent to a game.
+#[derive(Clone, Debug, Default)]
+pub struct AttachOptions {
+    /// The namespace plugins are written against, [`NAMED`](crate::mappings::NAMED) (the
+    /// default) or [`MOJMAP`](crate::mappings::MOJMAP).
+    pub source: Option<String>,
+    /// The namespace the game runs with, such as [`OFFICIAL`](crate::mappings::OFFICIAL) for
+    /// vanilla, if the agent shouldn't work it out; see [`MinecraftProcess::namespace`].
+    pub target: Option<String>,
+    /// Yarn's mappings for the agent to use instead of its cache and the network: a Tiny file,
+    /// or a directory of them named as in the cache (see
+    /// [`file_name`](crate::mappings::cache::MappingsKind::file_name)). The agent needs them
+    /// whatever the source namespace, since its own lookups use Yarn's names.
+    ///
+    /// To fill the cache itself instead, [`seed`](crate::mappings::cache::seed) it before
+    /// attaching.
+    pub mappings: Option<PathBuf>,
+    /// Mojang's mappings, a `client.txt` or a directory of them, which the agent needs with the
+    /// [`MOJMAP`](crate::mappings::MOJMAP) source if they aren't cached.
+    pub mojmap: Option<PathBuf>,
+}
+
+impl AttachOptions {
+    /// The arguments of the agent, starting with the library it loads.
+    fn agent_args(&self, lib: &Path) -> io::Result<String> {
+        let mut args = lib.display().to_string();
+        for (key, namespace) in [("source", &self.source), ("target", &self.target)] {
+            if let Some(namespace) = namespace {
+                args.push_str(&format!(";{}={}", key, namespace));
+            }
+        }
+        for (key, mappings) in [("mappings", &self.mappings), ("mojmap", &self.mojmap)] {
+            if let Some(mappings) = mappings {
+                // The game resolves relative paths against its own directory
+                let mappings = fs::canonicalize(mappings)?;
+                args.push_str(&format!(";{}={}", key, mappings.display()));
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// A connection to a running game.
+///
+/// Cloning is cheap and clones share the same session with the agent.
+#[derive(Clone)]
 pub struct MinecraftProcess {
     pub version: String,
     pub packet_manager: ClientPacketManager,
-    pub dotminecraft: PathBuf,
 }
 
 impl MinecraftProcess {
     pub async fn load(process: &Process, pid: &str, version: String) -> jni::errors::Result<Self> {
+        Self::load_with(process, pid, version, &AttachOptions::default()).await
+    }
+
+    pub async fn load_with(
+        process: &Process,
+        pid: &str,
+        version: String,
+        options: &AttachOptions,
+    ) -> jni::errors::Result<Self> {
         let agent_jar =
             write_agent_jar(process.cwd().unwrap()).expect("Failed to write embedded agent JAR");
 
@@ -55,6 +109,9 @@ impl MinecraftProcess {
             let out = process.cwd().unwrap().join("libagent.so");
             fs::copy(lib, &out).expect("Failed to copy library")
         });
+        let agent_args = options
+            .agent_args(&out)
+            .expect("Failed to find the mappings to attach with");
 
         let current_thread = thread::current();
 
@@ -83,7 +140,7 @@ impl MinecraftProcess {
                 .l()
                 .unwrap();
 
-            let arg = env.new_string(out.to_str().unwrap()).unwrap();
+            let arg = env.new_string(agent_args).unwrap();
             let agent_jstring = env.new_string(agent_jar).unwrap();
 
             current_thread.unpark();
@@ -115,10 +172,21 @@ impl MinecraftProcess {
         let stream = stream.unwrap();
         println!("Connected to agent at {}", ADDRESS);
 
+        Ok(Self::connect(stream, version)
+            .await
+            .expect("Handshake with agent failed"))
+    }
+
+    /// The namespace of the names the game runs with, as worked out by the agent.
+    pub fn namespace(&self) -> &str {
+        &self.packet_manager.welcome().namespace
+    }
+
+    /// Connects to an agent that is already listening on the other end of `stream`.
+    pub async fn connect<T: Transport>(stream: T, version: String) -> io::Result<Self> {
         Ok(Self {
-            packet_manager: ClientPacketManager::new(stream),
+            packet_manager: ClientPacketManager::new(stream).await?,
             version,
-            dotminecraft: process.cwd().unwrap().to_path_buf(),
         })
     }
 }
@@ -126,7 +194,7 @@ impl MinecraftProcess {
 fn get_mc_version(process: &Process) -> Option<String> {
     let idx = process.cmd().iter().position(|arg| arg == "-cp")?;
     let classpath = &process.cmd()[idx + 1];
-    let mc_path = Path::new(classpath.to_str().unwrap().split(':').last()?);
+    let mc_path = Path::new(classpath.to_str().unwrap().split(':').next_back()?);
     let jar_file = mc_path.file_name()?.to_str()?;
     Some(
         jar_file
@@ -147,6 +215,10 @@ fn load_jvm() -> JavaVM {
 }
 
 pub async fn find_and_connect() -> MinecraftProcess {
+    find_and_connect_with(&AttachOptions::default()).await
+}
+
+pub async fn find_and_connect_with(options: &AttachOptions) -> MinecraftProcess {
     let s = System::new_all();
     use std::ffi::OsStr;
     let mut processes = s
@@ -162,7 +234,7 @@ pub async fn find_and_connect() -> MinecraftProcess {
         .next()
         .unwrap_or_else(|| panic!("No Minecraft process found"));
     println!("Found Minecraft version: {}\nPID: {}", version, pid);
-    MinecraftProcess::load(process, &pid.to_string(), version.clone())
+    MinecraftProcess::load_with(process, &pid.to_string(), version.clone(), options)
         .await
         .unwrap()
 }
@@ -1,3 +1,13 @@
+pub mod bindings;
 pub mod communication;
+pub mod game_thread;
 pub mod initialization;
+pub mod mappings;
 pub mod minecraft;
+pub mod mock;
+pub mod panic;
+pub mod plugin;
+pub mod remote;
+
+#[doc(hidden)]
+pub use jni;
@@ -0,0 +1,119 @@
+//! Where the agent keeps the mappings it has downloaded, so attaching again doesn't need the
+//! network.
+//!
+//! Mappings are cached per game version under `$XDG_CACHE_HOME/mc-connect/mappings` when it is
+//! set, and under `.mc-connect/mappings` in the game directory otherwise. Both are looked in,
+//! the game directory first, so mappings the host [`seed`]s there win over those downloaded
+//! before.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const CACHE_DIR: &str = "mc-connect/mappings";
+const GAME_CACHE_DIR: &str = ".mc-connect/mappings";
+
+/// Which mappings of a game version a file holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MappingsKind {
+    /// Yarn's Tiny mappings, between the `official`, `intermediary` and `named` namespaces.
+    Yarn,
+    /// Mojang's ProGuard mappings, between the `mojmap` and `official` namespaces. The agent
+    /// doesn't download these.
+    Mojmap,
+}
+
+impl MappingsKind {
+    /// Name of the mappings of a game version, gzipped or not, in a cache or a directory of
+    /// mappings passed to the agent.
+    pub fn file_name(self, version: &str) -> String {
+        match self {
+            MappingsKind::Yarn => format!("yarn-{}.tiny", version),
+            MappingsKind::Mojmap => format!("mojmap-{}.txt", version),
+        }
+    }
+}
+
+impl FromStr for MappingsKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "yarn" => Ok(MappingsKind::Yarn),
+            "mojmap" => Ok(MappingsKind::Mojmap),
+            _ => Err(format!("unknown kind of mappings {}", kind)),
+        }
+    }
+}
+
+/// The cache directories of a game, from the one new mappings are stored in.
+pub fn dirs(game_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::with_capacity(2);
+    if let Some(cache_home) = env::var_os("XDG_CACHE_HOME").map(PathBuf::from) {
+        // Relative paths are invalid and should be ignored
+        if cache_home.is_absolute() {
+            dirs.push(cache_home.join(CACHE_DIR));
+        }
+    }
+    dirs.push(game_dir.join(GAME_CACHE_DIR));
+    dirs
+}
+
+/// Where mappings of `version` are stored once downloaded.
+pub fn path(game_dir: &Path, kind: MappingsKind, version: &str) -> PathBuf {
+    dirs(game_dir)[0].join(kind.file_name(version))
+}
+
+/// The cached mappings of `version`, if any, looking in the game directory first.
+pub fn find(game_dir: &Path, kind: MappingsKind, version: &str) -> Option<PathBuf> {
+    // The game directory is always last
+    dirs(game_dir)
+        .into_iter()
+        .rev()
+        .map(|dir| dir.join(kind.file_name(version)))
+        .find(|path| path.is_file())
+}
+
+/// Finds the mappings of `version` given as a file, which is used whatever its name, or as a
+/// directory containing them under [`MappingsKind::file_name`].
+pub fn find_local(local: &Path, kind: MappingsKind, version: &str) -> io::Result<PathBuf> {
+    if local.is_dir() {
+        let path = local.join(kind.file_name(version));
+        if !path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no mappings for {} in {}", version, local.display()),
+            ));
+        }
+        return Ok(path);
+    }
+    match fs::metadata(local) {
+        Ok(_) => Ok(local.to_path_buf()),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("failed to read {}: {}", local.display(), e),
+        )),
+    }
+}
+
+/// Copies mappings into the cache in a game's directory, replacing those of the same version.
+///
+/// The game directory is the cache the agent looks in first whatever its environment, so the
+/// seeded mappings are used even if others of the version were downloaded.
+pub fn seed(
+    game_dir: &Path,
+    kind: MappingsKind,
+    version: &str,
+    mappings: &Path,
+) -> io::Result<PathBuf> {
+    let dir = game_dir.join(GAME_CACHE_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(kind.file_name(version));
+    // Copy next to the cached file first so the agent never reads half of it
+    let partial = dir.join(format!("{}.part", kind.file_name(version)));
+    fs::copy(mappings, &partial)?;
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
@@ -0,0 +1,539 @@
+//! Names of classes and their members across namespaces, such as Yarn's `named` and the
+//! `intermediary` names the game runs with.
+//!
+//! Mappings are read from Tiny v1 or v2 files or from Mojang's ProGuard files, gzipped or not,
+//! and indexed by every namespace so lookups don't go through the JVM. Mojang's names are joined
+//! to Yarn's through the obfuscated names both map, with [`Mappings::compose`].
+
+pub mod cache;
+mod proguard;
+mod tiny;
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+
+/// Yarn's names.
+pub const NAMED: &str = "named";
+/// Fabric's names, which stay the same across game versions.
+pub const INTERMEDIARY: &str = "intermediary";
+/// The obfuscated names of the vanilla game.
+pub const OFFICIAL: &str = "official";
+/// Mojang's names.
+pub const MOJMAP: &str = "mojmap";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug)]
+pub enum MappingsError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    MissingNamespace(String),
+    /// Mappings being composed both have a namespace other than the one they are joined on.
+    DuplicateNamespace(String),
+}
+
+impl fmt::Display for MappingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MappingsError::Io(e) => write!(f, "failed to read mappings: {}", e),
+            MappingsError::Parse { line, message } => {
+                write!(f, "invalid mappings at line {}: {}", line, message)
+            }
+            MappingsError::MissingNamespace(namespace) => {
+                write!(f, "mappings have no {} namespace", namespace)
+            }
+            MappingsError::DuplicateNamespace(namespace) => {
+                write!(f, "both mappings have the {} namespace", namespace)
+            }
+        }
+    }
+}
+
+impl Error for MappingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MappingsError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MappingsError {
+    fn from(error: io::Error) -> Self {
+        MappingsError::Io(error)
+    }
+}
+
+pub struct Mappings {
+    namespaces: Vec<String>,
+    classes: Vec<ClassMapping>,
+    /// Index of every class by its name, for each namespace.
+    class_index: Vec<HashMap<String, usize>>,
+}
+
+pub struct ClassMapping {
+    names: Vec<String>,
+    methods: Vec<MemberMapping>,
+    fields: Vec<MemberMapping>,
+    method_index: Vec<HashMap<String, Vec<usize>>>,
+    field_index: Vec<HashMap<String, Vec<usize>>>,
+}
+
+pub struct MemberMapping {
+    names: Vec<String>,
+    /// Descriptor using class names of the first namespace.
+    desc: String,
+}
+
+impl Mappings {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, MappingsError> {
+        Self::parse(File::open(path)?)
+    }
+
+    /// Parses Tiny v1 or v2 or ProGuard mappings, which may be gzipped.
+    pub fn parse(reader: impl Read) -> Result<Self, MappingsError> {
+        let mut reader = BufReader::new(reader);
+        if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            Self::parse_uncompressed(BufReader::new(GzDecoder::new(reader)))
+        } else {
+            Self::parse_uncompressed(reader)
+        }
+    }
+
+    fn parse_uncompressed(mut reader: impl BufRead) -> Result<Self, MappingsError> {
+        let start = reader.fill_buf()?;
+        if start.starts_with(b"v1\t") || start.starts_with(b"tiny\t") {
+            tiny::parse(reader)
+        } else {
+            proguard::parse(reader)
+        }
+    }
+
+    fn new(namespaces: Vec<String>, mut classes: Vec<ClassMapping>) -> Self {
+        let mut class_index = vec![HashMap::with_capacity(classes.len()); namespaces.len()];
+        for (i, class) in classes.iter_mut().enumerate() {
+            for (ns, name) in class.names.iter().enumerate() {
+                class_index[ns].entry(name.clone()).or_insert(i);
+            }
+            class.method_index = index_members(&class.methods, namespaces.len());
+            class.field_index = index_members(&class.fields, namespaces.len());
+        }
+        Self {
+            namespaces,
+            classes,
+            class_index,
+        }
+    }
+
+    /// Joins mappings that share a namespace, such as Yarn's and Mojang's on [`OFFICIAL`], into
+    /// mappings with the namespaces of both.
+    ///
+    /// Classes and members missing from either side keep their name in the shared namespace
+    /// in that side's namespaces.
+    pub fn compose(&self, other: &Mappings, shared: &str) -> Result<Mappings, MappingsError> {
+        let missing = || MappingsError::MissingNamespace(shared.to_string());
+        let composer = Composer {
+            left: self,
+            right: other,
+            left_ns: self.namespace(shared).ok_or_else(missing)?,
+            right_ns: other.namespace(shared).ok_or_else(missing)?,
+        };
+        let mut namespaces = self.namespaces.clone();
+        for ns in composer.right_namespaces() {
+            if namespaces.contains(&other.namespaces[ns]) {
+                return Err(MappingsError::DuplicateNamespace(
+                    other.namespaces[ns].clone(),
+                ));
+            }
+            namespaces.push(other.namespaces[ns].clone());
+        }
+
+        let mut classes = Vec::with_capacity(self.classes.len());
+        let mut joined = vec![false; other.classes.len()];
+        for class in &self.classes {
+            let right_class = other.class_index[composer.right_ns]
+                .get(class.name(composer.left_ns))
+                .map(|&i| {
+                    joined[i] = true;
+                    &other.classes[i]
+                });
+            classes.push(composer.class(Some(class), right_class));
+        }
+        for (i, class) in other.classes.iter().enumerate() {
+            if !joined[i] {
+                classes.push(composer.class(None, Some(class)));
+            }
+        }
+        Ok(Mappings::new(namespaces, classes))
+    }
+
+    pub fn namespaces(&self) -> &[String] {
+        &self.namespaces
+    }
+
+    pub fn namespace(&self, namespace: &str) -> Option<usize> {
+        self.namespaces.iter().position(|ns| ns == namespace)
+    }
+
+    pub fn classes(&self) -> &[ClassMapping] {
+        &self.classes
+    }
+
+    pub fn class(&self, class_name: &str, ns: usize) -> Option<&ClassMapping> {
+        let i = *self.class_index.get(ns)?.get(class_name)?;
+        Some(&self.classes[i])
+    }
+
+    /// Maps a class name between namespaces, keeping names that aren't mapped, such as those of
+    /// the JDK.
+    pub fn map_class_name<'a>(&'a self, class_name: &'a str, from: usize, to: usize) -> &'a str {
+        match self.class(class_name, from) {
+            Some(class) => class.name(to),
+            None => class_name,
+        }
+    }
+
+    /// Maps the class names in a field or method descriptor between namespaces.
+    pub fn map_desc(&self, desc: &str, from: usize, to: usize) -> String {
+        let mut mapped = String::with_capacity(desc.len());
+        let mut rest = desc;
+        while let Some(start) = rest.find('L') {
+            let Some(end) = rest[start..].find(';') else {
+                break;
+            };
+            mapped.push_str(&rest[..=start]);
+            mapped.push_str(self.map_class_name(&rest[start + 1..start + end], from, to));
+            rest = &rest[start + end..];
+        }
+        mapped.push_str(rest);
+        mapped
+    }
+
+    /// Finds a method declared by a class, by its name and optionally its descriptor, all in
+    /// namespace `ns`.
+    pub fn method(
+        &self,
+        class_name: &str,
+        name: &str,
+        desc: Option<&str>,
+        ns: usize,
+    ) -> Option<&MemberMapping> {
+        let class = self.class(class_name, ns)?;
+        self.find_member(&class.methods, &class.method_index, name, desc, ns)
+    }
+
+    pub fn field(
+        &self,
+        class_name: &str,
+        name: &str,
+        desc: Option<&str>,
+        ns: usize,
+    ) -> Option<&MemberMapping> {
+        let class = self.class(class_name, ns)?;
+        self.find_member(&class.fields, &class.field_index, name, desc, ns)
+    }
+
+    fn find_member<'a>(
+        &self,
+        members: &'a [MemberMapping],
+        index: &[HashMap<String, Vec<usize>>],
+        name: &str,
+        desc: Option<&str>,
+        ns: usize,
+    ) -> Option<&'a MemberMapping> {
+        let candidates = index.get(ns)?.get(name)?;
+        let Some(desc) = desc else {
+            return Some(&members[candidates[0]]);
+        };
+        let desc = self.map_desc(desc, ns, 0);
+        candidates
+            .iter()
+            .map(|&i| &members[i])
+            .find(|member| member.desc == desc)
+    }
+}
+
+/// Joins classes and members of two mappings on a namespace they share.
+struct Composer<'a> {
+    left: &'a Mappings,
+    right: &'a Mappings,
+    left_ns: usize,
+    right_ns: usize,
+}
+
+impl Composer<'_> {
+    fn right_namespaces(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.right.namespaces.len()).filter(|&ns| ns != self.right_ns)
+    }
+
+    /// Names in the namespaces of both sides, where at least one side is given.
+    fn names(&self, left: Option<&[String]>, right: Option<&[String]>) -> Vec<String> {
+        let shared = match (left, right) {
+            (Some(left), _) => &left[self.left_ns],
+            (None, Some(right)) => &right[self.right_ns],
+            (None, None) => unreachable!("nothing to join"),
+        };
+        let mut names = match left {
+            Some(left) => left.to_vec(),
+            None => vec![shared.clone(); self.left.namespaces.len()],
+        };
+        names.extend(self.right_namespaces().map(|ns| match right {
+            Some(right) => right[ns].clone(),
+            None => shared.clone(),
+        }));
+        names
+    }
+
+    fn class(&self, left: Option<&ClassMapping>, right: Option<&ClassMapping>) -> ClassMapping {
+        let mut class = ClassMapping::new(self.names(
+            left.map(|class| class.names.as_slice()),
+            right.map(|class| class.names.as_slice()),
+        ));
+        class.methods = self.members(
+            left.map_or(&[], |class| &class.methods),
+            right.map_or(&[], |class| &class.methods),
+        );
+        class.fields = self.members(
+            left.map_or(&[], |class| &class.fields),
+            right.map_or(&[], |class| &class.fields),
+        );
+        class
+    }
+
+    /// Joins members by their name and descriptor in the shared namespace.
+    fn members(&self, left: &[MemberMapping], right: &[MemberMapping]) -> Vec<MemberMapping> {
+        let mut right_index = HashMap::with_capacity(right.len());
+        for (i, member) in right.iter().enumerate() {
+            let desc = self.right.map_desc(&member.desc, 0, self.right_ns);
+            right_index.insert((member.names[self.right_ns].as_str(), desc), i);
+        }
+
+        let mut joined = vec![false; right.len()];
+        let mut members = Vec::with_capacity(left.len().max(right.len()));
+        for member in left {
+            let desc = self.left.map_desc(&member.desc, 0, self.left_ns);
+            let right_member = right_index
+                .get(&(member.names[self.left_ns].as_str(), desc))
+                .map(|&i| {
+                    joined[i] = true;
+                    right[i].names.as_slice()
+                });
+            members.push(MemberMapping {
+                names: self.names(Some(&member.names), right_member),
+                desc: member.desc.clone(),
+            });
+        }
+        for (i, member) in right.iter().enumerate() {
+            if joined[i] {
+                continue;
+            }
+            // Descriptors are in the first namespace, which is the left side's
+            let desc = self.right.map_desc(&member.desc, 0, self.right_ns);
+            members.push(MemberMapping {
+                names: self.names(None, Some(&member.names)),
+                desc: self.left.map_desc(&desc, self.left_ns, 0),
+            });
+        }
+        members
+    }
+}
+
+fn index_members(members: &[MemberMapping], namespaces: usize) -> Vec<HashMap<String, Vec<usize>>> {
+    let mut index = vec![HashMap::<String, Vec<usize>>::new(); namespaces];
+    for (i, member) in members.iter().enumerate() {
+        for (ns, name) in member.names.iter().enumerate() {
+            index[ns].entry(name.clone()).or_default().push(i);
+        }
+    }
+    index
+}
+
+impl ClassMapping {
+    fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            methods: Vec::new(),
+            fields: Vec::new(),
+            method_index: Vec::new(),
+            field_index: Vec::new(),
+        }
+    }
+
+    pub fn name(&self, ns: usize) -> &str {
+        &self.names[ns]
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn methods(&self) -> &[MemberMapping] {
+        &self.methods
+    }
+
+    pub fn fields(&self) -> &[MemberMapping] {
+        &self.fields
+    }
+}
+
+impl MemberMapping {
+    pub fn name(&self, ns: usize) -> &str {
+        &self.names[ns]
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Descriptor using class names of the first namespace; see [`Mappings::map_desc`].
+    pub fn desc(&self) -> &str {
+        &self.desc
+    }
+}
+
+/// Fills in names left empty, which are the same as in the first namespace.
+fn fill_names(mut names: Vec<String>, count: usize) -> Option<Vec<String>> {
+    if names.first().is_none_or(String::is_empty) || names.len() > count {
+        return None;
+    }
+    names.resize(count, String::new());
+    for i in 1..count {
+        if names[i].is_empty() {
+            names[i] = names[0].clone();
+        }
+    }
+    Some(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yarn() -> Mappings {
+        Mappings::parse(
+            "tiny\t2\t0\tofficial\tintermediary\tnamed\n\
+             c\ta\tnet/minecraft/class_1\tnet/minecraft/Foo\n\
+             \tm\t(La;Lb;)La;\tc\tmethod_2\tcombine\n\
+             \tf\t[La;\td\tfield_3\tchildren\n\
+             c\tb\tnet/minecraft/class_4\tnet/minecraft/Bar\n\
+             c\te\tnet/minecraft/class_5\tnet/minecraft/OnlyYarn\n"
+                .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    fn mojmap() -> Mappings {
+        Mappings::parse(
+            "tiny\t2\t0\tmojmap\tofficial\n\
+             c\tnet/minecraft/MojFoo\ta\n\
+             \tm\t(Lnet/minecraft/MojFoo;Lnet/minecraft/MojBar;)Lnet/minecraft/MojFoo;\tmojCombine\tc\n\
+             \tm\t()V\tonlyMojmap\tf\n\
+             c\tnet/minecraft/MojBar\tb\n\
+             c\tnet/minecraft/OnlyMojmap\tg\n"
+                .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn maps_class_names() {
+        let mappings = yarn();
+        assert_eq!(
+            mappings.map_class_name("net/minecraft/class_1", 1, 2),
+            "net/minecraft/Foo"
+        );
+        assert_eq!(mappings.map_class_name("net/minecraft/Bar", 2, 0), "b");
+        assert_eq!(
+            mappings.map_class_name("java/lang/String", 2, 1),
+            "java/lang/String"
+        );
+    }
+
+    #[test]
+    fn maps_descriptors() {
+        let mappings = yarn();
+        assert_eq!(
+            mappings.map_desc("(La;[[Lb;IJ)La;", 0, 2),
+            "(Lnet/minecraft/Foo;[[Lnet/minecraft/Bar;IJ)Lnet/minecraft/Foo;"
+        );
+        // Classes that aren't mapped, such as the JDK's, pass through
+        assert_eq!(
+            mappings.map_desc("(Ljava/lang/String;ZLnet/minecraft/class_4;)V", 1, 2),
+            "(Ljava/lang/String;ZLnet/minecraft/Bar;)V"
+        );
+        assert_eq!(mappings.map_desc("[D", 0, 2), "[D");
+    }
+
+    #[test]
+    fn finds_members_by_descriptor_in_any_namespace() {
+        let mappings = yarn();
+        let method = mappings.method(
+            "net/minecraft/Foo",
+            "combine",
+            Some("(Lnet/minecraft/Foo;Lnet/minecraft/Bar;)Lnet/minecraft/Foo;"),
+            2,
+        );
+        assert_eq!(method.unwrap().name(1), "method_2");
+        assert!(
+            mappings
+                .method("net/minecraft/Foo", "combine", Some("()V"), 2)
+                .is_none()
+        );
+        let field = mappings.field("net/minecraft/class_1", "field_3", None, 1);
+        assert_eq!(field.unwrap().name(2), "children");
+    }
+
+    #[test]
+    fn composes_on_a_shared_namespace() {
+        let composed = yarn().compose(&mojmap(), OFFICIAL).unwrap();
+        assert_eq!(
+            composed.namespaces(),
+            ["official", "intermediary", "named", "mojmap"]
+        );
+        let mojmap = composed.namespace(MOJMAP).unwrap();
+
+        let foo = composed.class("net/minecraft/MojFoo", mojmap).unwrap();
+        assert_eq!(foo.name(2), "net/minecraft/Foo");
+        // Members are joined by their official descriptor, and keep descriptors of the first namespace
+        let combine = composed
+            .method("net/minecraft/MojFoo", "mojCombine", None, mojmap)
+            .unwrap();
+        assert_eq!(combine.name(2), "combine");
+        assert_eq!(combine.desc(), "(La;Lb;)La;");
+        let only_mojmap = composed
+            .method("net/minecraft/MojFoo", "onlyMojmap", Some("()V"), mojmap)
+            .unwrap();
+        assert_eq!(only_mojmap.names(), ["f", "f", "f", "onlyMojmap"]);
+        let children = composed.field("a", "d", None, 0).unwrap();
+        assert_eq!(children.name(mojmap), "d");
+
+        // Classes on one side only keep their official name on the other
+        let only_yarn = composed.class("net/minecraft/OnlyYarn", 2).unwrap();
+        assert_eq!(only_yarn.name(mojmap), "e");
+        let only_mojmap = composed.class("net/minecraft/OnlyMojmap", mojmap).unwrap();
+        assert_eq!(only_mojmap.names()[..3], ["g", "g", "g"]);
+    }
+
+    #[test]
+    fn refuses_to_compose_without_a_shared_namespace() {
+        assert!(matches!(
+            yarn().compose(&mojmap(), INTERMEDIARY),
+            Err(MappingsError::MissingNamespace(ns)) if ns == INTERMEDIARY
+        ));
+        assert!(matches!(
+            yarn().compose(&yarn(), OFFICIAL),
+            Err(MappingsError::DuplicateNamespace(ns)) if ns == INTERMEDIARY
+        ));
+    }
+}
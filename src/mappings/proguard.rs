@@ -0,0 +1,239 @@
+//! Reading ProGuard files, which Mojang publishes its names in as `client.txt`.
+//!
+//! They map Mojang's names to the obfuscated ones the vanilla game runs with, and become
+//! mappings from [`MOJMAP`] to [`OFFICIAL`].
+
+use std::{collections::HashSet, io::BufRead};
+
+use super::{ClassMapping, MOJMAP, Mappings, MappingsError, MemberMapping, OFFICIAL};
+
+fn error(line: usize, message: &str) -> MappingsError {
+    MappingsError::Parse {
+        line: line + 1,
+        message: message.to_string(),
+    }
+}
+
+pub fn parse(reader: impl BufRead) -> Result<Mappings, MappingsError> {
+    let mut classes = Vec::<ClassMapping>::new();
+    // Methods are listed again for every range of lines inlined into them
+    let mut members = HashSet::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let (from, to) = trimmed
+            .split_once(" -> ")
+            .ok_or_else(|| error(i, "missing ` -> `"))?;
+
+        if !line.starts_with(char::is_whitespace) {
+            let to = to
+                .strip_suffix(':')
+                .ok_or_else(|| error(i, "class without `:`"))?;
+            classes.push(ClassMapping::new(vec![
+                from.replace('.', "/"),
+                to.replace('.', "/"),
+            ]));
+            members.clear();
+            continue;
+        }
+
+        let Some(class) = classes.last_mut() else {
+            return Err(error(i, "member outside of a class"));
+        };
+        let (ty, name) = from
+            .split_once(' ')
+            .ok_or_else(|| error(i, "member without a type"))?;
+        match name.split_once('(') {
+            Some((name, rest)) => {
+                let params = rest
+                    .split_once(')')
+                    .ok_or_else(|| error(i, "unclosed parameters"))?
+                    .0;
+                // Line numbers come before the return type
+                let ret = ty.rsplit(':').next().unwrap_or(ty);
+                let mut desc = String::from("(");
+                for param in params.split(',').filter(|param| !param.is_empty()) {
+                    desc.push_str(&type_desc(param));
+                }
+                desc.push(')');
+                desc.push_str(&type_desc(ret));
+                if members.insert((true, name.to_string(), desc.clone())) {
+                    class.methods.push(MemberMapping {
+                        names: vec![name.to_string(), to.to_string()],
+                        desc,
+                    });
+                }
+            }
+            None => {
+                let desc = type_desc(ty);
+                if members.insert((false, name.to_string(), desc.clone())) {
+                    class.fields.push(MemberMapping {
+                        names: vec![name.to_string(), to.to_string()],
+                        desc,
+                    });
+                }
+            }
+        }
+    }
+    Ok(Mappings::new(
+        vec![MOJMAP.to_string(), OFFICIAL.to_string()],
+        classes,
+    ))
+}
+
+/// `java.lang.String[]` becomes `[Ljava/lang/String;`.
+fn type_desc(ty: &str) -> String {
+    let element = ty.trim_end_matches("[]");
+    let mut desc = "[".repeat((ty.len() - element.len()) / 2);
+    let primitive = match element {
+        "boolean" => 'Z',
+        "byte" => 'B',
+        "char" => 'C',
+        "short" => 'S',
+        "int" => 'I',
+        "long" => 'J',
+        "float" => 'F',
+        "double" => 'D',
+        "void" => 'V',
+        _ => {
+            desc.push('L');
+            desc.push_str(&element.replace('.', "/"));
+            desc.push(';');
+            return desc;
+        }
+    };
+    desc.push(primitive);
+    desc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappings::{INTERMEDIARY, NAMED};
+
+    const CLIENT_TXT: &str = "\
+# {\"id\":\"sourceFile\",\"fileName\":\"client.txt\"}
+net.minecraft.world.Foo -> a:
+    int count -> b
+    net.minecraft.world.Foo$Inner[][] grid -> c
+    1:1:void <init>() -> <init>
+    12:15:boolean tick(long,java.lang.String[],byte) -> d
+    16:16:boolean tick(long,java.lang.String[],byte):40:40 -> d
+    20:25:net.minecraft.world.Foo$Inner inner(int[],char) -> e
+net.minecraft.world.Foo$Inner -> a$a:
+    double value -> a
+";
+
+    fn parse_str(text: &str) -> Mappings {
+        parse(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn parses_classes_and_members() {
+        let mappings = parse_str(CLIENT_TXT);
+        assert_eq!(mappings.namespaces(), [MOJMAP, OFFICIAL]);
+        assert_eq!(mappings.classes().len(), 2);
+
+        let inner = mappings.class("net/minecraft/world/Foo$Inner", 0).unwrap();
+        assert_eq!(inner.name(1), "a$a");
+        assert_eq!(inner.fields()[0].desc(), "D");
+
+        let foo = mappings.class("a", 1).unwrap();
+        let fields = foo.fields();
+        assert_eq!(fields[0].names(), ["count", "b"]);
+        assert_eq!(fields[0].desc(), "I");
+        assert_eq!(fields[1].desc(), "[[Lnet/minecraft/world/Foo$Inner;");
+    }
+
+    #[test]
+    fn builds_method_descriptors_without_line_numbers() {
+        let mappings = parse_str(CLIENT_TXT);
+        let foo = mappings.class("net/minecraft/world/Foo", 0).unwrap();
+        let methods = foo
+            .methods()
+            .iter()
+            .map(|method| (method.name(0), method.name(1), method.desc()))
+            .collect::<Vec<_>>();
+        // The inlined range of `tick` is the same method
+        assert_eq!(
+            methods,
+            [
+                ("<init>", "<init>", "()V"),
+                ("tick", "d", "(J[Ljava/lang/String;B)Z"),
+                ("inner", "e", "([IC)Lnet/minecraft/world/Foo$Inner;"),
+            ]
+        );
+    }
+
+    #[test]
+    fn composes_with_intermediary() {
+        let yarn = Mappings::parse(
+            "tiny\t2\t0\tofficial\tintermediary\tnamed\n\
+             c\ta\tnet/minecraft/class_1\tnet/minecraft/Foo\n\
+             \tm\t(J[Ljava/lang/String;B)Z\td\tmethod_2\ttick\n\
+             \tm\t([IC)La$a;\te\tmethod_3\tgetInner\n\
+             \tf\t[[La$a;\tc\tfield_4\tgrid\n\
+             c\ta$a\tnet/minecraft/class_1$class_5\tnet/minecraft/Foo$Inner\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let composed = yarn.compose(&parse_str(CLIENT_TXT), OFFICIAL).unwrap();
+        let (intermediary, named, mojmap) = (
+            composed.namespace(INTERMEDIARY).unwrap(),
+            composed.namespace(NAMED).unwrap(),
+            composed.namespace(MOJMAP).unwrap(),
+        );
+
+        assert_eq!(
+            composed.map_class_name("net/minecraft/world/Foo$Inner", mojmap, intermediary),
+            "net/minecraft/class_1$class_5"
+        );
+        let inner = composed
+            .method(
+                "net/minecraft/world/Foo",
+                "inner",
+                Some("([IC)Lnet/minecraft/world/Foo$Inner;"),
+                mojmap,
+            )
+            .unwrap();
+        assert_eq!(inner.name(intermediary), "method_3");
+        assert_eq!(inner.name(named), "getInner");
+        assert_eq!(
+            composed.map_desc(inner.desc(), 0, mojmap),
+            "([IC)Lnet/minecraft/world/Foo$Inner;"
+        );
+        let tick = composed
+            .method("net/minecraft/class_1", "method_2", None, intermediary)
+            .unwrap();
+        assert_eq!(tick.name(mojmap), "tick");
+        let grid = composed
+            .field("net/minecraft/world/Foo", "grid", None, mojmap)
+            .unwrap();
+        assert_eq!(grid.name(intermediary), "field_4");
+        // Only Mojang names the field, which keeps its official name elsewhere
+        let count = composed
+            .field("net/minecraft/world/Foo", "count", Some("I"), mojmap)
+            .unwrap();
+        assert_eq!(count.name(intermediary), "b");
+    }
+
+    #[test]
+    fn refuses_malformed_lines() {
+        let line_of = |text: &str| match parse(text.as_bytes()) {
+            Err(MappingsError::Parse { line, .. }) => line,
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("parsed {:?}", text),
+        };
+        assert_eq!(line_of("net.minecraft.Foo a:\n"), 1);
+        assert_eq!(line_of("net.minecraft.Foo -> a\n"), 1);
+        assert_eq!(line_of("    int count -> b\n"), 1);
+        assert_eq!(line_of("net.minecraft.Foo -> a:\n    count -> b\n"), 2);
+        assert_eq!(
+            line_of("net.minecraft.Foo -> a:\n    void tick(int -> b\n"),
+            2
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Reading Tiny files: v1, which Yarn is downloaded as, and v2, which it is built with.
+
+use std::{collections::HashMap, io::BufRead};
+
+use super::{ClassMapping, Mappings, MappingsError, MemberMapping, fill_names};
+
+fn error(line: usize, message: &str) -> MappingsError {
+    MappingsError::Parse {
+        line: line + 1,
+        message: message.to_string(),
+    }
+}
+
+pub fn parse(reader: impl BufRead) -> Result<Mappings, MappingsError> {
+    let mut lines = reader.lines().enumerate();
+    let header = match lines.next() {
+        Some((_, line)) => line?,
+        None => return Err(error(0, "empty file")),
+    };
+    let mut header = header.split('\t');
+    let namespaces = match (header.next(), header.next()) {
+        (Some("v1"), Some(first)) => std::iter::once(first).chain(header),
+        (Some("tiny"), Some("2")) => {
+            // The minor version comes before the namespaces
+            header.next();
+            let namespaces = header.map(str::to_string).collect::<Vec<_>>();
+            check_namespaces(&namespaces)?;
+            return parse_v2(lines, namespaces);
+        }
+        _ => return Err(error(0, "not a Tiny v1 or v2 file")),
+    };
+    let namespaces = namespaces.map(str::to_string).collect::<Vec<_>>();
+    check_namespaces(&namespaces)?;
+    parse_v1(lines, namespaces)
+}
+
+fn check_namespaces(namespaces: &[String]) -> Result<(), MappingsError> {
+    if namespaces.len() < 2 {
+        return Err(error(0, "less than two namespaces"));
+    }
+    Ok(())
+}
+
+type Lines<R> = std::iter::Enumerate<std::io::Lines<R>>;
+
+/// Tiny v1 has a line per class and member, with members naming their class by its first name.
+fn parse_v1<R: BufRead>(
+    lines: Lines<R>,
+    namespaces: Vec<String>,
+) -> Result<Mappings, MappingsError> {
+    let mut classes = Vec::new();
+    let mut class_index = HashMap::new();
+    let mut class = |classes: &mut Vec<ClassMapping>, name: &str| {
+        *class_index.entry(name.to_string()).or_insert_with(|| {
+            classes.push(ClassMapping::new(vec![name.to_string(); namespaces.len()]));
+            classes.len() - 1
+        })
+    };
+
+    for (i, line) in lines {
+        let line = line?;
+        let mut columns = line.split('\t');
+        let kind = columns.next().unwrap_or_default();
+        let names = |columns: std::str::Split<'_, char>| {
+            let names = columns.map(str::to_string).collect();
+            fill_names(names, namespaces.len()).ok_or_else(|| error(i, "missing name"))
+        };
+        match kind {
+            "CLASS" => {
+                let names = names(columns)?;
+                let i = class(&mut classes, &names[0]);
+                classes[i].names = names;
+            }
+            "METHOD" | "FIELD" => {
+                let owner = columns.next().ok_or_else(|| error(i, "missing class"))?;
+                let desc = columns
+                    .next()
+                    .ok_or_else(|| error(i, "missing descriptor"))?;
+                let member = MemberMapping {
+                    desc: desc.to_string(),
+                    names: names(columns)?,
+                };
+                let owner = class(&mut classes, owner);
+                if kind == "METHOD" {
+                    classes[owner].methods.push(member);
+                } else {
+                    classes[owner].fields.push(member);
+                }
+            }
+            // Comments and anything a later version may add
+            _ => {}
+        }
+    }
+    Ok(Mappings::new(namespaces, classes))
+}
+
+/// Tiny v2 nests members under their class by indentation.
+fn parse_v2<R: BufRead>(
+    lines: Lines<R>,
+    namespaces: Vec<String>,
+) -> Result<Mappings, MappingsError> {
+    let mut classes = Vec::<ClassMapping>::new();
+    let mut escaped_names = false;
+    for (i, line) in lines {
+        let line = line?;
+        let depth = line.bytes().take_while(|&b| b == b'\t').count();
+        let mut columns = line[depth..].split('\t');
+        let kind = columns.next().unwrap_or_default();
+        let names = |columns: std::str::Split<'_, char>| {
+            let names = columns
+                .map(|name| {
+                    if escaped_names {
+                        unescape(name)
+                    } else {
+                        name.to_string()
+                    }
+                })
+                .collect();
+            fill_names(names, namespaces.len()).ok_or_else(|| error(i, "missing name"))
+        };
+
+        match (depth, kind) {
+            (0, "c") => classes.push(ClassMapping::new(names(columns)?)),
+            // Properties come before the first class
+            (1, "escaped-names") if classes.is_empty() => escaped_names = true,
+            (1, "m" | "f") => {
+                let Some(class) = classes.last_mut() else {
+                    return Err(error(i, "member outside of a class"));
+                };
+                let desc = columns
+                    .next()
+                    .ok_or_else(|| error(i, "missing descriptor"))?;
+                let member = MemberMapping {
+                    desc: desc.to_string(),
+                    names: names(columns)?,
+                };
+                if kind == "m" {
+                    class.methods.push(member);
+                } else {
+                    class.fields.push(member);
+                }
+            }
+            // Comments, parameters, local variables and unknown properties
+            _ => {}
+        }
+    }
+    Ok(Mappings::new(namespaces, classes))
+}
+
+fn unescape(name: &str) -> String {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('0') => unescaped.push('\0'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(text: &str) -> Result<Mappings, MappingsError> {
+        parse(text.as_bytes())
+    }
+
+    #[test]
+    fn parses_v1() {
+        let mappings = parse_str(
+            "v1\tofficial\tintermediary\tnamed\n\
+             # a comment\n\
+             CLASS\ta\tnet/minecraft/class_1\tnet/minecraft/Foo\n\
+             METHOD\ta\t(La;I)V\tb\tmethod_2\tbar\n\
+             FIELD\ta\tJ\tc\tfield_3\t\n\
+             METHOD\td\t()V\te\tmethod_4\tbaz\n",
+        )
+        .unwrap();
+        assert_eq!(mappings.namespaces(), ["official", "intermediary", "named"]);
+
+        let class = mappings.class("net/minecraft/Foo", 2).unwrap();
+        assert_eq!(
+            class.names(),
+            ["a", "net/minecraft/class_1", "net/minecraft/Foo"]
+        );
+        let method = &class.methods()[0];
+        assert_eq!(method.names(), ["b", "method_2", "bar"]);
+        assert_eq!(method.desc(), "(La;I)V");
+        // Names left empty are the first namespace's
+        assert_eq!(class.fields()[0].names(), ["c", "field_3", "c"]);
+
+        // A member's class doesn't need a line of its own
+        let unlisted = mappings.class("d", 0).unwrap();
+        assert_eq!(unlisted.names(), ["d", "d", "d"]);
+        assert_eq!(unlisted.methods()[0].name(2), "baz");
+    }
+
+    #[test]
+    fn parses_v2() {
+        let mappings = parse_str(
+            "tiny\t2\t0\tofficial\tintermediary\tnamed\n\
+             c\ta\tnet/minecraft/class_1\tnet/minecraft/Foo\n\
+             \tc\tA comment on the class\n\
+             \tm\t(La;)V\tb\tmethod_2\tbar\n\
+             \t\tp\t1\t\t\tvalue\n\
+             \tf\tI\tc\tfield_3\tcount\n\
+             c\td\tnet/minecraft/class_4\t\n",
+        )
+        .unwrap();
+        assert_eq!(mappings.namespaces(), ["official", "intermediary", "named"]);
+
+        let class = mappings.class("net/minecraft/class_1", 1).unwrap();
+        assert_eq!(class.name(2), "net/minecraft/Foo");
+        assert_eq!(class.methods().len(), 1);
+        assert_eq!(class.methods()[0].names(), ["b", "method_2", "bar"]);
+        assert_eq!(class.methods()[0].desc(), "(La;)V");
+        assert_eq!(class.fields()[0].names(), ["c", "field_3", "count"]);
+        assert_eq!(mappings.class("d", 0).unwrap().name(2), "d");
+    }
+
+    #[test]
+    fn unescapes_v2_names_when_asked_to() {
+        let text = "tiny\t2\t0\tofficial\tnamed\n\
+                    \tescaped-names\n\
+                    c\ta\tFoo\\tBar\\\\\n";
+        let mappings = parse_str(text).unwrap();
+        assert_eq!(mappings.classes()[0].name(1), "Foo\tBar\\");
+
+        let mappings = parse_str(&text.replace("\tescaped-names\n", "")).unwrap();
+        assert_eq!(mappings.classes()[0].name(1), "Foo\\tBar\\\\");
+    }
+
+    #[test]
+    fn refuses_malformed_files() {
+        let line_of = |text| match parse_str(text) {
+            Err(MappingsError::Parse { line, .. }) => line,
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("parsed {:?}", text),
+        };
+        assert_eq!(line_of(""), 1);
+        assert_eq!(line_of("tiny\t3\t0\tofficial\tnamed\n"), 1);
+        assert_eq!(line_of("v1\tofficial\n"), 1);
+        assert_eq!(line_of("v1\tofficial\tnamed\nCLASS\n"), 2);
+        assert_eq!(line_of("v1\tofficial\tnamed\nCLASS\ta\tb\tc\n"), 2);
+        assert_eq!(line_of("v1\tofficial\tnamed\nMETHOD\ta\n"), 2);
+        assert_eq!(line_of("tiny\t2\t0\tofficial\tnamed\n\tm\t()V\ta\tb\n"), 2);
+        assert_eq!(line_of("tiny\t2\t0\tofficial\tnamed\nc\ta\tb\n\tf\n"), 3);
+    }
+}
@@ -1,34 +1,283 @@
-use std::{fmt, fs, io, path::PathBuf};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Write},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+};
 
-use jni::{AttachGuard, JavaVM, objects::JClass};
+use bincode::Encode;
+use jni::{AttachGuard, JNIEnv, JavaVM, objects::JClass};
+use tokio::sync::{broadcast, oneshot};
 
 use crate::{
-    communication::{PacketSendResult, PrintPacket, RunPacket, ToastPacket},
+    communication::{
+        CallPacket, CustomPacket, DownloadPacket, Event, EventKind, GameThread, GetFieldPacket,
+        InvokePacket, LoadPacket, Packet, PacketSendResult, PrintPacket, RunPacket, SchedulePacket,
+        SetFieldPacket, ToastPacket, UploadPacket,
+        transfer::{CHUNK_SIZE, TransferError, validate_relative_path},
+    },
+    game_thread,
     initialization::MinecraftProcess,
+    plugin::plugin_name,
 };
 
 impl MinecraftProcess {
-    pub async fn print(&mut self, message: &str) -> io::Result<PacketSendResult> {
+    pub async fn print(&self, message: &str) -> io::Result<PacketSendResult> {
         let packet = PrintPacket::new(message.to_string());
         self.packet_manager.send_packet(&packet.as_header()).await
     }
 
-    pub async fn println(&mut self, message: &str) -> io::Result<PacketSendResult> {
+    pub async fn println(&self, message: &str) -> io::Result<PacketSendResult> {
         let packet = PrintPacket::new(message.to_string() + "\n");
         self.packet_manager.send_packet(&packet.as_header()).await
     }
 
-    pub async fn toast(&mut self, title: &str, body: &str) -> io::Result<PacketSendResult> {
+    /// Sends `value` to the plugin handling `channel`, encoded with bincode.
+    pub async fn send_custom<T: Encode>(
+        &self,
+        channel: &str,
+        value: &T,
+    ) -> io::Result<PacketSendResult> {
+        let packet = CustomPacket::encode(channel.to_string(), value)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    /// Takes the agent's exclusive lock; other sessions' requests fail until it is released.
+    pub async fn lock(&self) -> io::Result<PacketSendResult> {
+        self.packet_manager
+            .send_packet(&Packet::Lock.as_header())
+            .await
+    }
+
+    pub async fn unlock(&self) -> io::Result<PacketSendResult> {
+        self.packet_manager
+            .send_packet(&Packet::Unlock.as_header())
+            .await
+    }
+
+    pub async fn subscribe(&self, kind: EventKind) -> io::Result<PacketSendResult> {
+        let packet = Packet::Subscribe(kind);
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    pub async fn unsubscribe(&self, kind: EventKind) -> io::Result<PacketSendResult> {
+        let packet = Packet::Unsubscribe(kind);
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    /// Requests this connection's session id, the open sessions and the lock owner.
+    pub async fn sessions(&self) -> io::Result<PacketSendResult> {
+        self.packet_manager
+            .send_packet(&Packet::Sessions.as_header())
+            .await
+    }
+
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.packet_manager.events()
+    }
+
+    /// Calls a game method on the agent's network thread, answered with a [`Packet::Value`].
+    ///
+    /// Most game state may only be touched from a game thread; wrap the packet with
+    /// [`schedule`](Self::schedule) for those calls.
+    pub async fn invoke(&self, invoke: InvokePacket) -> io::Result<PacketSendResult> {
+        let packet = Packet::Invoke(invoke);
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    /// Reads a field on the agent's network thread, answered with a [`Packet::Value`].
+    pub async fn get_field(&self, get_field: GetFieldPacket) -> io::Result<PacketSendResult> {
+        let packet = Packet::GetField(get_field);
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    /// Writes a field on the agent's network thread, answered with a confirmation.
+    pub async fn set_field(&self, set_field: SetFieldPacket) -> io::Result<PacketSendResult> {
+        let packet = Packet::SetField(Box::new(set_field));
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    /// Has the agent handle `packet` on `thread` instead of its network thread.
+    ///
+    /// A packet that needs the other game thread, such as a toast scheduled on the server
+    /// thread, is answered with an error.
+    pub async fn schedule(
+        &self,
+        thread: GameThread,
+        packet: Packet,
+    ) -> io::Result<PacketSendResult> {
+        let packet = SchedulePacket::new(thread, packet);
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    pub async fn toast(&self, title: &str, body: &str) -> io::Result<PacketSendResult> {
         let packet = ToastPacket::new(title.to_string(), body.to_string());
         self.packet_manager.send_packet(&packet.as_header()).await
     }
 
-    pub async fn run(&mut self, lib: PathBuf, func: String) -> io::Result<PacketSendResult> {
-        let new_loc = self.dotminecraft.join(lib.file_name().unwrap());
-        fs::copy(lib, &new_loc)?;
-        let packet = RunPacket::new(new_loc, func);
+    /// Uploads a plugin and calls `func` with `value` encoded with bincode, loading the plugin
+    /// or reloading it if a plugin of the same name is loaded. Answered with a
+    /// [`Packet::Return`].
+    pub async fn run<T: Encode>(
+        &self,
+        lib: PathBuf,
+        func: String,
+        value: &T,
+    ) -> Result<PacketSendResult, TransferError> {
+        let payload = encode_payload(value)?;
+        let remote = self.upload_plugin(&lib).await?;
+        let packet = RunPacket::new(remote, func, payload);
+        Ok(self.packet_manager.send_packet(&packet.as_header()).await?)
+    }
+
+    /// Uploads a plugin and loads it, answered with a [`Packet::Plugin`].
+    ///
+    /// The plugin is named after the library's file name without its extension.
+    pub async fn load_plugin(&self, lib: &Path) -> Result<PacketSendResult, TransferError> {
+        let remote = self.upload_plugin(lib).await?;
+        let packet = Packet::Load(LoadPacket { lib: remote });
+        Ok(self.packet_manager.send_packet(&packet.as_header()).await?)
+    }
+
+    /// Calls a function a loaded plugin exports with `value` encoded with bincode, answered
+    /// with a [`Packet::Return`].
+    pub async fn call_plugin<T: Encode>(
+        &self,
+        plugin: &str,
+        entry: &str,
+        value: &T,
+    ) -> io::Result<PacketSendResult> {
+        let packet = Packet::Call(CallPacket {
+            plugin: plugin.to_string(),
+            entry: entry.to_string(),
+            payload: encode_payload(value)?,
+        });
+        self.packet_manager.send_packet(&packet.as_header()).await
+    }
+
+    pub async fn unload_plugin(&self, plugin: &str) -> io::Result<PacketSendResult> {
+        let packet = Packet::Unload(plugin.to_string());
         self.packet_manager.send_packet(&packet.as_header()).await
     }
+
+    /// Uploads a new build of a loaded plugin and reloads it, answered with a
+    /// [`Packet::Plugin`].
+    pub async fn reload_plugin(&self, lib: &Path) -> Result<PacketSendResult, TransferError> {
+        let name = plugin_name(lib).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "library path has no file name")
+        })?;
+        self.upload_plugin(lib).await?;
+        let packet = Packet::Reload(name);
+        Ok(self.packet_manager.send_packet(&packet.as_header()).await?)
+    }
+
+    /// Lists the loaded plugins, answered with a [`Packet::PluginList`].
+    pub async fn plugins(&self) -> io::Result<PacketSendResult> {
+        self.packet_manager
+            .send_packet(&Packet::Plugins.as_header())
+            .await
+    }
+
+    async fn upload_plugin(&self, lib: &Path) -> Result<PathBuf, TransferError> {
+        let file_name = lib.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "library path has no file name")
+        })?;
+        self.upload(lib, &Path::new("plugins").join(file_name))
+            .await
+    }
+
+    /// Uploads a local file into the agent's file directory, returning its path on the agent's side.
+    pub async fn upload(&self, local: &Path, remote: &Path) -> Result<PathBuf, TransferError> {
+        validate_relative_path(remote)?;
+        let mut file = File::open(local)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut offset = 0;
+        loop {
+            let read = read_chunk(&mut file, &mut buf)?;
+            hasher.update(&buf[..read]);
+            let is_last = read < buf.len();
+            let packet = Packet::Upload(UploadPacket {
+                path: remote.to_path_buf(),
+                offset,
+                data: buf[..read].to_vec(),
+                checksum: is_last.then(|| hasher.clone().finalize()),
+            });
+            let response = self
+                .packet_manager
+                .send_packet(&packet.as_header())
+                .await?
+                .get_result()
+                .await?;
+            offset += read as u64;
+            match response {
+                Packet::Confirmation if !is_last => {}
+                Packet::File(info) if is_last => return Ok(info.path),
+                packet => return Err(TransferError::UnexpectedResponse(packet)),
+            }
+        }
+    }
+
+    /// Downloads a file from the game directory, returning the number of bytes written.
+    pub async fn download(&self, remote: &Path, local: &Path) -> Result<u64, TransferError> {
+        validate_relative_path(remote)?;
+        let mut file = File::create(local)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut offset = 0;
+        let mut expected = None;
+        loop {
+            let packet = Packet::Download(DownloadPacket {
+                path: remote.to_path_buf(),
+                offset,
+                length: CHUNK_SIZE as u64,
+            });
+            let chunk = match self
+                .packet_manager
+                .send_packet(&packet.as_header())
+                .await?
+                .get_result()
+                .await?
+            {
+                Packet::FileChunk(chunk) => chunk,
+                packet => return Err(TransferError::UnexpectedResponse(packet)),
+            };
+            let (size, checksum) = *expected.get_or_insert((chunk.size, chunk.checksum));
+            // The file may have grown since the download started; only keep the original size
+            let data = &chunk.data[..chunk.data.len().min((size - offset) as usize)];
+            file.write_all(data)?;
+            hasher.update(data);
+            offset += data.len() as u64;
+
+            if offset >= size || data.is_empty() {
+                if offset != size || checksum != Some(hasher.finalize()) {
+                    drop(file);
+                    let _ = std::fs::remove_file(local);
+                    return Err(TransferError::ChecksumMismatch {
+                        path: remote.to_path_buf(),
+                    });
+                }
+                return Ok(size);
+            }
+        }
+    }
+}
+
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn encode_payload<T: Encode>(value: &T) -> io::Result<Vec<u8>> {
+    bincode::encode_to_vec(value, bincode::config::standard())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -70,4 +319,31 @@ impl<'a> MinecraftContext<'a> {
             agent_class,
         })
     }
+
+    /// Queues `task` on one of the game's threads; see [`game_thread::execute`].
+    pub fn execute<F, R>(
+        &mut self,
+        thread: GameThread,
+        task: F,
+    ) -> jni::errors::Result<oneshot::Receiver<R>>
+    where
+        F: FnOnce(&mut JNIEnv) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        game_thread::execute(&mut self.env, thread, task)
+    }
+}
+
+impl<'a> Deref for MinecraftContext<'a> {
+    type Target = JNIEnv<'a>;
+
+    fn deref(&self) -> &JNIEnv<'a> {
+        &self.env
+    }
+}
+
+impl<'a> DerefMut for MinecraftContext<'a> {
+    fn deref_mut(&mut self) -> &mut JNIEnv<'a> {
+        &mut self.env
+    }
 }
@@ -0,0 +1,204 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+use crate::{
+    communication::{Event, Packet, codec::Transport, server::ServerPacketManager},
+    initialization::MinecraftProcess,
+    mappings::INTERMEDIARY,
+};
+
+/// Size of the in-memory pipe between a mock agent and a client connected to it.
+const DUPLEX_BUFFER_SIZE: usize = 1024 * 1024;
+
+type Handler = Box<dyn FnMut(&Packet) -> Option<Packet> + Send>;
+
+/// An in-process stand-in for the agent, for testing host code without a running game.
+///
+/// Requests are answered by the first registered handler that returns a response, or with
+/// [`Packet::Confirmation`] if none does. Every request is kept for later assertions.
+#[derive(Clone, Default)]
+pub struct MockAgent {
+    handlers: Arc<Mutex<Vec<Handler>>>,
+    received: Arc<Mutex<Vec<Packet>>>,
+    connections: Arc<Mutex<Vec<Arc<ServerPacketManager>>>>,
+}
+
+impl MockAgent {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a handler that may answer a request; handlers are tried in the order they were added.
+    pub fn on<F>(&self, handler: F) -> &Self
+    where
+        F: FnMut(&Packet) -> Option<Packet> + Send + 'static,
+    {
+        self.handlers.lock().unwrap().push(Box::new(handler));
+        self
+    }
+
+    /// Answers every request matching `predicate` with `response`.
+    pub fn respond<P>(&self, predicate: P, response: Packet) -> &Self
+    where
+        P: Fn(&Packet) -> bool + Send + 'static,
+    {
+        self.on(move |packet| predicate(packet).then(|| response.clone()))
+    }
+
+    /// Every request received so far, in order.
+    pub fn received(&self) -> Vec<Packet> {
+        self.received.lock().unwrap().clone()
+    }
+
+    pub fn clear_received(&self) {
+        self.received.lock().unwrap().clear();
+    }
+
+    /// Panics unless `packet` has been received.
+    pub fn assert_received(&self, packet: &Packet) {
+        let received = self.received();
+        assert!(
+            received.contains(packet),
+            "expected mock agent to receive {:?}, received {:?}",
+            packet,
+            received
+        );
+    }
+
+    /// Sends an event to every connected client, forgetting those that have disconnected.
+    pub async fn send_event(&self, event: Event) {
+        let connections = self.connections.lock().unwrap().clone();
+        for connection in connections {
+            if connection
+                .send_packet(Packet::Event(event.clone()))
+                .await
+                .is_err()
+            {
+                self.connections
+                    .lock()
+                    .unwrap()
+                    .retain(|other| !Arc::ptr_eq(other, &connection));
+            }
+        }
+    }
+
+    /// Serves a single client on the other end of `stream`.
+    pub async fn serve<T: Transport>(&self, stream: T) -> io::Result<()> {
+        // Like a game installed with Fabric
+        let packet_manager = ServerPacketManager::new(stream, INTERMEDIARY).await?;
+        let handlers = Arc::clone(&self.handlers);
+        let received = Arc::clone(&self.received);
+        packet_manager.start_listening(move |packet| {
+            received.lock().unwrap().push(packet.clone());
+            handlers
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .find_map(|handler| handler(&packet))
+                .unwrap_or(Packet::Confirmation)
+        });
+        self.connections
+            .lock()
+            .unwrap()
+            .push(Arc::new(packet_manager));
+        Ok(())
+    }
+
+    /// Accepts clients on a local socket, returning the address it is bound to.
+    pub async fn bind(&self, addr: impl ToSocketAddrs) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let this = self.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                if let Err(e) = this.serve(socket).await {
+                    eprintln!("Mock agent handshake failed: {}", e);
+                }
+            }
+        });
+        Ok(local_addr)
+    }
+
+    /// Creates a `MinecraftProcess` connected to this mock over an in-memory pipe.
+    pub async fn process(&self, version: &str) -> io::Result<MinecraftProcess> {
+        let (client, server) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+        let this = self.clone();
+        let serving = tokio::spawn(async move { this.serve(server).await });
+        let process = MinecraftProcess::connect(client, version.to_string()).await?;
+        serving.await.map_err(io::Error::other)??;
+        Ok(process)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::communication::{PacketSendResult, codec::PacketCodec, handshake};
+
+    async fn result(request: io::Result<PacketSendResult>) -> Packet {
+        request.unwrap().get_result().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn answers_requests() {
+        let agent = MockAgent::new();
+        agent.respond(
+            |packet| matches!(packet, Packet::Plugins),
+            Packet::PluginList(Vec::new()),
+        );
+        let process = agent.process("1.21").await.unwrap();
+
+        assert_eq!(process.namespace(), INTERMEDIARY);
+        assert_eq!(
+            result(process.plugins().await).await,
+            Packet::PluginList(Vec::new())
+        );
+        assert_eq!(
+            result(process.println("hi").await).await,
+            Packet::Confirmation
+        );
+        agent.assert_received(&Packet::Plugins);
+        assert_eq!(agent.received().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sends_events() {
+        let agent = MockAgent::new();
+        let process = agent.process("1.21").await.unwrap();
+        let mut events = process.events();
+
+        let event = Event::SessionOpened { session: 1 };
+        agent.send_event(event.clone()).await;
+        let received = tokio::time::timeout(Duration::from_secs(5), events.recv()).await;
+        assert_eq!(received.unwrap().unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn forgets_disconnected_clients() {
+        let agent = MockAgent::new();
+        let (client, server) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+        let connect = async {
+            let mut framed = PacketCodec::new().framed(client);
+            handshake::connect(&mut framed).await.unwrap();
+        };
+        let (serving, ()) = tokio::join!(agent.serve(server), connect);
+        serving.unwrap();
+        let process = agent.process("1.21").await.unwrap();
+        let mut events = process.events();
+        assert_eq!(agent.connections.lock().unwrap().len(), 2);
+
+        // The first client is gone, which doesn't keep the event from the second
+        let event = Event::SessionClosed { session: 2 };
+        agent.send_event(event.clone()).await;
+        let received = tokio::time::timeout(Duration::from_secs(5), events.recv()).await;
+        assert_eq!(received.unwrap().unwrap(), event);
+        assert_eq!(agent.connections.lock().unwrap().len(), 1);
+    }
+}
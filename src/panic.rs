@@ -0,0 +1,143 @@
+//! Stopping panics at the boundaries between the game, the agent and plugins.
+//!
+//! Unwinding out of an `extern "C"` or JNI function aborts the game, so every function the JVM
+//! or another library calls runs its body with [`catch`] and reports the [`Panic`] some other
+//! way: as an error packet, as a Java exception, or as an error
+//! [`PluginResult`](crate::plugin::PluginResult) with [`guard`](crate::plugin::guard).
+//!
+//! The first call to [`catch`] installs a panic hook that records where a panic happened and
+//! its backtrace, then runs the hook that was installed before.
+
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::RefCell,
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe, PanicHookInfo},
+    sync::Once,
+};
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// Functions between a panic and the hook.
+const PANIC_FRAMES: &[&str] = &[
+    "std::panicking::",
+    "core::panicking::",
+    "__rustc::",
+    "std::sys::backtrace::__rust_end_short_backtrace",
+];
+
+thread_local! {
+    /// Location and backtrace of the last panic on this thread.
+    static LAST_PANIC: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) };
+}
+
+/// A panic caught by [`catch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Panic {
+    pub message: String,
+    /// Where the panic happened, if the hook saw it.
+    pub location: Option<String>,
+    /// One line per line of the backtrace, empty if none was captured.
+    pub backtrace: Vec<String>,
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked")?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for Panic {}
+
+impl Panic {
+    /// The panic followed by its backtrace, for where only a message can be reported.
+    pub fn with_backtrace(&self) -> String {
+        let mut message = self.to_string();
+        for line in &self.backtrace {
+            message.push_str("\n    ");
+            message.push_str(line);
+        }
+        message
+    }
+}
+
+/// Runs `body`, catching a panic instead of letting it unwind into the caller.
+///
+/// Whatever `body` was changing when it panicked is left as it was, so state it shares with
+/// later calls must stay usable when a call is cut short.
+pub fn catch<F, R>(body: F) -> Result<R, Panic>
+where
+    F: FnOnce() -> R,
+{
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            record(info);
+            previous(info);
+        }));
+    });
+    LAST_PANIC.with_borrow_mut(|last| *last = None);
+
+    panic::catch_unwind(AssertUnwindSafe(body)).map_err(|payload| {
+        let (location, backtrace) = match LAST_PANIC.with_borrow_mut(Option::take) {
+            Some((location, backtrace)) => (Some(location), backtrace_lines(&backtrace)),
+            None => (None, Vec::new()),
+        };
+        Panic {
+            message: message(payload.as_ref()),
+            location,
+            backtrace,
+        }
+    })
+}
+
+fn record(info: &PanicHookInfo) {
+    let location = info
+        .location()
+        .map_or_else(|| "<unknown>".to_string(), ToString::to_string);
+    let backtrace = Backtrace::force_capture();
+    LAST_PANIC.with_borrow_mut(|last| *last = Some((location, backtrace)));
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+/// Leaves out the hook and the functions that panicked on the way to it, like a short
+/// backtrace does, unless there are no names to tell them apart by.
+fn backtrace_lines(backtrace: &Backtrace) -> Vec<String> {
+    let mut lines = backtrace
+        .to_string()
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect::<Vec<_>>();
+    let mut panicking = false;
+    let start = lines.iter().position(|line| match frame_name(line) {
+        Some(name) if PANIC_FRAMES.iter().any(|prefix| name.starts_with(prefix)) => {
+            panicking = true;
+            false
+        }
+        Some(_) => panicking,
+        None => false,
+    });
+    lines.split_off(start.unwrap_or(0))
+}
+
+/// Frames start with their number, and are followed by the code they were at.
+fn frame_name(line: &str) -> Option<&str> {
+    let (number, name) = line.split_once(": ")?;
+    number.parse::<usize>().ok()?;
+    Some(name)
+}
@@ -0,0 +1,297 @@
+//! The ABI between the agent and plugin libraries loaded into the game.
+//!
+//! A panic must not unwind out of any function the agent calls, or the game aborts, so plugins
+//! define those functions with the macros here, which run their bodies with [`guard`] or
+//! [`panic::catch`].
+//!
+//! A plugin can handle [`CustomPacket`](crate::communication::CustomPacket)s by registering a
+//! handler for each of its channels with [`register!`], which exports [`REGISTER_SYMBOL`]:
+//!
+//! ```ignore
+//! plugin::register!(|registrar| registrar.register("example:echo", echo));
+//!
+//! plugin::handler! {
+//!     fn echo(_jvm, payload) {
+//!         PluginResult::ok(payload.as_slice().to_vec())
+//!     }
+//! }
+//! ```
+//!
+//! Entry points called by a [`RunPacket`](crate::communication::RunPacket) or a
+//! [`CallPacket`](crate::communication::CallPacket) are defined with [`entry!`]. What they
+//! return answers the packet as a [`ReturnPacket`](crate::communication::ReturnPacket), an
+//! error or a panic as an error packet:
+//!
+//! ```ignore
+//! plugin::entry! {
+//!     fn divide(_jvm, payload) {
+//!         let config = bincode::config::standard();
+//!         let Ok(((a, b), _)) = bincode::decode_from_slice::<(i32, i32), _>(payload.as_slice(), config)
+//!         else {
+//!             return PluginResult::err("expected two integers");
+//!         };
+//!         PluginResult::ok(bincode::encode_to_vec(a / b, config).unwrap())
+//!     }
+//! }
+//! ```
+//!
+//! A plugin stays loaded until it is unloaded or reloaded. Before that, the agent calls the
+//! function [`on_unload!`] exports as [`ON_UNLOAD_SYMBOL`], if any, which must stop whatever
+//! threads or callbacks the plugin left running:
+//!
+//! ```ignore
+//! plugin::on_unload!(|_jvm| WORKER.lock().unwrap().stop());
+//! ```
+
+use std::{ffi::c_void, marker::PhantomData, mem::ManuallyDrop, path::Path, ptr, slice};
+
+use jni::JavaVM;
+
+use crate::panic;
+
+/// Prefix of the functions the agent calls itself, such as [`REGISTER_SYMBOL`], which can't be
+/// called as entry points.
+pub const RESERVED_PREFIX: &str = "mc_connect_";
+
+/// Name of the function a plugin exports to register its channel handlers.
+pub const REGISTER_SYMBOL: &str = "mc_connect_register";
+
+pub type RegisterFn = extern "C" fn(&mut ChannelRegistrar);
+
+/// Name of the function a plugin exports to clean up before it is unloaded.
+pub const ON_UNLOAD_SYMBOL: &str = "mc_connect_on_unload";
+
+pub type OnUnloadFn = extern "C" fn(&JavaVM);
+
+/// An entry point of a plugin, called by name with the payload of the packet that called it.
+pub type EntryFn = extern "C" fn(&JavaVM, PluginSlice) -> PluginResult;
+
+/// Name of the plugin loaded from `lib`, which is its file name without the extension.
+pub fn plugin_name(lib: &Path) -> Option<String> {
+    Some(lib.file_stem()?.to_str()?.to_string())
+}
+
+/// Handles the payload of a custom packet sent on a registered channel.
+pub type ChannelHandler = extern "C" fn(&JavaVM, PluginSlice) -> PluginResult;
+
+/// Bytes borrowed from the caller for the duration of a call.
+#[repr(C)]
+pub struct PluginSlice<'a> {
+    ptr: *const u8,
+    len: usize,
+    _marker: PhantomData<&'a [u8]>,
+}
+
+impl<'a> PluginSlice<'a> {
+    pub fn as_slice(&self) -> &'a [u8] {
+        // SAFETY: only ever created from a live slice of the same lifetime
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<'a> From<&'a [u8]> for PluginSlice<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Frees the parts of a `Vec<u8>` with the allocator of the library the function is compiled
+/// into.
+type FreeFn = unsafe extern "C" fn(ptr: *mut u8, len: usize, capacity: usize);
+
+/// Bytes whose ownership moves to the receiver.
+///
+/// The bytes are freed by the library that allocated them, so a plugin may use another global
+/// allocator than the agent, but it must stay loaded until the buffer is dropped.
+#[repr(C)]
+pub struct PluginBuffer {
+    ptr: *mut u8,
+    len: usize,
+    capacity: usize,
+    free: FreeFn,
+}
+
+impl PluginBuffer {
+    /// Takes the bytes, copying them if another library allocated them.
+    pub fn into_vec(self) -> Vec<u8> {
+        if ptr::fn_addr_eq(self.free, free_vec as FreeFn) {
+            let this = ManuallyDrop::new(self);
+            // SAFETY: created from a `Vec<u8>` that was forgotten, with this library's allocator
+            unsafe { Vec::from_raw_parts(this.ptr, this.len, this.capacity) }
+        } else {
+            // SAFETY: only ever created from a live `Vec<u8>`, which `drop` frees afterwards
+            unsafe { slice::from_raw_parts(self.ptr, self.len) }.to_vec()
+        }
+    }
+}
+
+impl From<Vec<u8>> for PluginBuffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        let mut bytes = ManuallyDrop::new(bytes);
+        Self {
+            ptr: bytes.as_mut_ptr(),
+            len: bytes.len(),
+            capacity: bytes.capacity(),
+            free: free_vec,
+        }
+    }
+}
+
+impl Drop for PluginBuffer {
+    fn drop(&mut self) {
+        // SAFETY: `free` comes from the library that allocated the bytes
+        unsafe { (self.free)(self.ptr, self.len, self.capacity) }
+    }
+}
+
+unsafe extern "C" fn free_vec(ptr: *mut u8, len: usize, capacity: usize) {
+    // SAFETY: only called by `PluginBuffer` with the parts of the `Vec<u8>` it was created from
+    drop(unsafe { Vec::from_raw_parts(ptr, len, capacity) });
+}
+
+/// Either a payload or, on failure, a UTF-8 error message.
+#[repr(C)]
+pub struct PluginResult {
+    is_ok: bool,
+    data: PluginBuffer,
+}
+
+impl PluginResult {
+    pub fn ok(payload: Vec<u8>) -> Self {
+        Self {
+            is_ok: true,
+            data: payload.into(),
+        }
+    }
+
+    pub fn err(message: impl Into<String>) -> Self {
+        Self {
+            is_ok: false,
+            data: message.into().into_bytes().into(),
+        }
+    }
+
+    pub fn into_result(self) -> Result<Vec<u8>, String> {
+        let data = self.data.into_vec();
+        if self.is_ok {
+            Ok(data)
+        } else {
+            Err(String::from_utf8_lossy(&data).into_owned())
+        }
+    }
+}
+
+/// Runs the body of a handler or entry point, turning a panic into an error that carries its
+/// backtrace.
+pub fn guard<F>(body: F) -> PluginResult
+where
+    F: FnOnce() -> PluginResult,
+{
+    panic::catch(body).unwrap_or_else(|panic| PluginResult::err(panic.with_backtrace()))
+}
+
+/// Defines entry points, exported under their names and run with [`guard`](crate::plugin::guard).
+#[macro_export]
+macro_rules! plugin_entry {
+    ($($(#[$attr:meta])* fn $name:ident($jvm:pat_param, $payload:pat_param) $body:block)*) => {
+        $(
+            $(#[$attr])*
+            #[unsafe(no_mangle)]
+            pub extern "C" fn $name(
+                $jvm: &$crate::jni::JavaVM,
+                $payload: $crate::plugin::PluginSlice,
+            ) -> $crate::plugin::PluginResult {
+                $crate::plugin::guard(|| $body)
+            }
+        )*
+    };
+}
+
+/// Defines channel handlers run with [`guard`](crate::plugin::guard), like
+/// [`entry!`](crate::plugin::entry) but without exporting them.
+#[macro_export]
+macro_rules! plugin_handler {
+    ($($(#[$attr:meta])* $vis:vis fn $name:ident($jvm:pat_param, $payload:pat_param) $body:block)*) => {
+        $(
+            $(#[$attr])*
+            $vis extern "C" fn $name(
+                $jvm: &$crate::jni::JavaVM,
+                $payload: $crate::plugin::PluginSlice,
+            ) -> $crate::plugin::PluginResult {
+                $crate::plugin::guard(|| $body)
+            }
+        )*
+    };
+}
+
+/// Exports the [`REGISTER_SYMBOL`](crate::plugin::REGISTER_SYMBOL) function, printing a panic
+/// instead of letting it unwind.
+#[macro_export]
+macro_rules! plugin_register {
+    (|$registrar:pat_param| $body:expr) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn mc_connect_register($registrar: &mut $crate::plugin::ChannelRegistrar) {
+            if let Err(panic) = $crate::panic::catch(|| $body) {
+                eprintln!("Failed to register channels: {}", panic.with_backtrace());
+            }
+        }
+    };
+}
+
+/// Exports the [`ON_UNLOAD_SYMBOL`](crate::plugin::ON_UNLOAD_SYMBOL) function, printing a panic
+/// instead of letting it unwind.
+#[macro_export]
+macro_rules! plugin_on_unload {
+    (|$jvm:pat_param| $body:expr) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn mc_connect_on_unload($jvm: &$crate::jni::JavaVM) {
+            if let Err(panic) = $crate::panic::catch(|| $body) {
+                eprintln!("Failed to unload: {}", panic.with_backtrace());
+            }
+        }
+    };
+}
+
+pub use crate::{
+    plugin_entry as entry, plugin_handler as handler, plugin_on_unload as on_unload,
+    plugin_register as register,
+};
+
+impl From<Result<Vec<u8>, String>> for PluginResult {
+    fn from(result: Result<Vec<u8>, String>) -> Self {
+        match result {
+            Ok(payload) => PluginResult::ok(payload),
+            Err(message) => PluginResult::err(message),
+        }
+    }
+}
+
+/// Passed to a plugin's [`REGISTER_SYMBOL`] function to collect its channel handlers.
+#[repr(C)]
+pub struct ChannelRegistrar {
+    context: *mut c_void,
+    register: extern "C" fn(*mut c_void, PluginSlice, ChannelHandler),
+}
+
+impl ChannelRegistrar {
+    /// Creates a registrar that calls `register` with `context` for every handler.
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid for whatever `register` does with it for as long as the
+    /// registrar is in use.
+    pub unsafe fn new(
+        context: *mut c_void,
+        register: extern "C" fn(*mut c_void, PluginSlice, ChannelHandler),
+    ) -> Self {
+        Self { context, register }
+    }
+
+    pub fn register(&mut self, channel: &str, handler: ChannelHandler) {
+        (self.register)(self.context, channel.as_bytes().into(), handler);
+    }
+}
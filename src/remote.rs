@@ -0,0 +1,297 @@
+//! Calling into the game from the host, and holding on to the objects it returns.
+
+use std::{error::Error, fmt, io};
+
+use crate::{
+    communication::{
+        GetFieldPacket, InvokePacket, InvokeTarget, JavaValue, ObjectHandle, Packet, RequestError,
+        SetFieldPacket,
+        value::{JavaType, UnexpectedValue},
+    },
+    initialization::MinecraftProcess,
+};
+
+#[derive(Debug)]
+pub enum CallError {
+    Io(io::Error),
+    Request(RequestError),
+    /// The result wasn't the kind of value the caller asked for.
+    UnexpectedValue(UnexpectedValue),
+    UnexpectedResponse(Packet),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::Io(e) => write!(f, "remote call failed: {}", e),
+            CallError::Request(e) => write!(f, "remote call failed: {}", e),
+            CallError::UnexpectedValue(e) => write!(f, "unexpected result of remote call: {}", e),
+            CallError::UnexpectedResponse(packet) => {
+                write!(f, "unexpected response to remote call: {:?}", packet)
+            }
+        }
+    }
+}
+
+impl Error for CallError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CallError::Io(e) => Some(e),
+            CallError::Request(e) => Some(e),
+            CallError::UnexpectedValue(e) => Some(e),
+            CallError::UnexpectedResponse(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for CallError {
+    fn from(error: io::Error) -> Self {
+        CallError::Io(error)
+    }
+}
+
+impl From<RequestError> for CallError {
+    fn from(error: RequestError) -> Self {
+        CallError::Request(error)
+    }
+}
+
+impl From<UnexpectedValue> for CallError {
+    fn from(error: UnexpectedValue) -> Self {
+        CallError::UnexpectedValue(error)
+    }
+}
+
+/// A game object the agent keeps alive for this session until the guard is dropped.
+///
+/// Dropping the guard releases the handle in the background, which needs a Tokio runtime;
+/// without one the handle is only released when the session ends.
+pub struct RemoteObject {
+    process: MinecraftProcess,
+    handle: ObjectHandle,
+    class_name: String,
+}
+
+impl RemoteObject {
+    /// Takes ownership of the object handle in `value`.
+    pub fn new(process: &MinecraftProcess, value: JavaValue) -> Result<Self, UnexpectedValue> {
+        match value {
+            JavaValue::Object { handle, class_name } => Ok(Self {
+                process: process.clone(),
+                handle,
+                class_name,
+            }),
+            found => Err(UnexpectedValue {
+                expected: JavaType::Object("java/lang/Object".to_string()),
+                found,
+            }),
+        }
+    }
+
+    pub fn handle(&self) -> ObjectHandle {
+        self.handle
+    }
+
+    /// Name of the object's runtime class in the source namespace.
+    pub fn class_name(&self) -> &str {
+        &self.class_name
+    }
+
+    /// The object as an argument to a call.
+    pub fn to_value(&self) -> JavaValue {
+        JavaValue::Object {
+            handle: self.handle,
+            class_name: self.class_name.clone(),
+        }
+    }
+
+    /// Calls a method of the object by its name and descriptor in the source namespace.
+    pub async fn call(
+        &self,
+        method_name: &str,
+        desc: &str,
+        args: Vec<JavaValue>,
+    ) -> Result<JavaValue, CallError> {
+        let packet = InvokePacket {
+            class_name: self.class_name.clone(),
+            method_name: method_name.to_string(),
+            desc: desc.to_string(),
+            target: InvokeTarget::Object(self.handle),
+            args,
+        };
+        self.process.call(packet).await
+    }
+
+    /// Like [`call`](Self::call), for methods returning an object without a value representation.
+    pub async fn call_object(
+        &self,
+        method_name: &str,
+        desc: &str,
+        args: Vec<JavaValue>,
+    ) -> Result<RemoteObject, CallError> {
+        let value = self.call(method_name, desc, args).await?;
+        Ok(RemoteObject::new(&self.process, value)?)
+    }
+
+    /// Reads a field of the object by its name and descriptor in the source namespace.
+    pub async fn get_field(&self, field_name: &str, desc: &str) -> Result<JavaValue, CallError> {
+        let packet = GetFieldPacket {
+            class_name: self.class_name.clone(),
+            field_name: field_name.to_string(),
+            desc: desc.to_string(),
+            target: InvokeTarget::Object(self.handle),
+        };
+        self.process.read_field(packet).await
+    }
+
+    pub async fn get_field_object(
+        &self,
+        field_name: &str,
+        desc: &str,
+    ) -> Result<RemoteObject, CallError> {
+        let value = self.get_field(field_name, desc).await?;
+        Ok(RemoteObject::new(&self.process, value)?)
+    }
+
+    /// Writes a field of the object by its name and descriptor in the source namespace.
+    pub async fn set_field(
+        &self,
+        field_name: &str,
+        desc: &str,
+        value: JavaValue,
+    ) -> Result<(), CallError> {
+        let packet = SetFieldPacket {
+            class_name: self.class_name.clone(),
+            field_name: field_name.to_string(),
+            desc: desc.to_string(),
+            target: InvokeTarget::Object(self.handle),
+            value,
+        };
+        self.process.write_field(packet).await
+    }
+}
+
+impl fmt::Debug for RemoteObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteObject")
+            .field("handle", &self.handle)
+            .field("class_name", &self.class_name)
+            .finish()
+    }
+}
+
+impl Drop for RemoteObject {
+    fn drop(&mut self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let packet_manager = self.process.packet_manager.clone();
+        let packet = Packet::Release(self.handle);
+        runtime.spawn(async move {
+            // Waiting for the confirmation keeps it from being reported as unexpected
+            if let Ok(result) = packet_manager.send_packet(&packet.as_header()).await {
+                let _ = result.get_result().await;
+            }
+        });
+    }
+}
+
+impl MinecraftProcess {
+    /// Calls a static method by its name and descriptor in the source namespace.
+    pub async fn call_static(
+        &self,
+        class_name: &str,
+        method_name: &str,
+        desc: &str,
+        args: Vec<JavaValue>,
+    ) -> Result<JavaValue, CallError> {
+        let packet = InvokePacket {
+            class_name: class_name.to_string(),
+            method_name: method_name.to_string(),
+            desc: desc.to_string(),
+            target: InvokeTarget::Static,
+            args,
+        };
+        self.call(packet).await
+    }
+
+    pub async fn call_static_object(
+        &self,
+        class_name: &str,
+        method_name: &str,
+        desc: &str,
+        args: Vec<JavaValue>,
+    ) -> Result<RemoteObject, CallError> {
+        let value = self
+            .call_static(class_name, method_name, desc, args)
+            .await?;
+        Ok(RemoteObject::new(self, value)?)
+    }
+
+    /// Reads a static field by its name and descriptor in the source namespace.
+    pub async fn get_static_field(
+        &self,
+        class_name: &str,
+        field_name: &str,
+        desc: &str,
+    ) -> Result<JavaValue, CallError> {
+        let packet = GetFieldPacket {
+            class_name: class_name.to_string(),
+            field_name: field_name.to_string(),
+            desc: desc.to_string(),
+            target: InvokeTarget::Static,
+        };
+        self.read_field(packet).await
+    }
+
+    pub async fn get_static_field_object(
+        &self,
+        class_name: &str,
+        field_name: &str,
+        desc: &str,
+    ) -> Result<RemoteObject, CallError> {
+        let value = self.get_static_field(class_name, field_name, desc).await?;
+        Ok(RemoteObject::new(self, value)?)
+    }
+
+    pub async fn set_static_field(
+        &self,
+        class_name: &str,
+        field_name: &str,
+        desc: &str,
+        value: JavaValue,
+    ) -> Result<(), CallError> {
+        let packet = SetFieldPacket {
+            class_name: class_name.to_string(),
+            field_name: field_name.to_string(),
+            desc: desc.to_string(),
+            target: InvokeTarget::Static,
+            value,
+        };
+        self.write_field(packet).await
+    }
+
+    async fn call(&self, packet: InvokePacket) -> Result<JavaValue, CallError> {
+        let response = self.invoke(packet).await?.get_result().await?;
+        value_response(response)
+    }
+
+    async fn read_field(&self, packet: GetFieldPacket) -> Result<JavaValue, CallError> {
+        let response = self.get_field(packet).await?.get_result().await?;
+        value_response(response)
+    }
+
+    async fn write_field(&self, packet: SetFieldPacket) -> Result<(), CallError> {
+        match self.set_field(packet).await?.get_result().await? {
+            Packet::Confirmation => Ok(()),
+            packet => Err(CallError::UnexpectedResponse(packet)),
+        }
+    }
+}
+
+fn value_response(response: Packet) -> Result<JavaValue, CallError> {
+    match response {
+        Packet::Value(value) => Ok(value),
+        packet => Err(CallError::UnexpectedResponse(packet)),
+    }
+}